@@ -1,5 +1,5 @@
-use axum_socket_io::Notifier;
-use std::{collections::HashMap, sync::LazyLock};
+use axum_socket_io::{Broadcast, Notifier};
+use std::sync::LazyLock;
 use tokio::sync::mpsc::{self, Sender};
 
 pub enum Room {
@@ -17,7 +17,7 @@ impl Room {
 pub static TASK: LazyLock<Sender<Room>> = LazyLock::new(|| {
     let (tx, mut rx) = mpsc::channel::<Room>(16);
     tokio::spawn(async move {
-        let mut main_room = HashMap::new();
+        let mut main_room = Broadcast::new();
 
         while let Some(action) = rx.recv().await {
             match action {
@@ -25,9 +25,7 @@ pub static TASK: LazyLock<Sender<Room>> = LazyLock::new(|| {
                     main_room.insert(id, notifier);
                 }
                 Room::Broadcast(ev, msg) => {
-                    for user in main_room.values() {
-                        user.notify(ev, &msg).await.unwrap();
-                    }
+                    main_room.broadcast_concurrent(ev, msg, 16).await.unwrap();
                 }
                 Room::Leave { id } => {
                     main_room.remove(&id);
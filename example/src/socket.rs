@@ -34,9 +34,11 @@ pub async fn handle_socket(mut socket: SocketIo, addr: SocketAddr) {
                 }
                 _ => {}
             },
+            Procedure::StreamCall(..) | Procedure::Heartbeat(_) => {}
         }
     }
 
     println!("user disconnected: {addr:#?}");
     Room::Leave { id }.dispatch().await;
+    let _ = socket.close(1000, "").await;
 }
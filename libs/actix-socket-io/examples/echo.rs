@@ -0,0 +1,34 @@
+//! Replies to every call with its own payload.
+//!
+//! Run with `cargo run -p actix-socket-io --example echo`, then connect to `ws://127.0.0.1:3000/socket`.
+
+use actix_socket_io::{Procedure, SocketIoUpgrade};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use std::io;
+
+async fn ws_handler(upgrade: SocketIoUpgrade) -> HttpResponse {
+    upgrade.on_upgrade(16, |mut socket| async move {
+        while let Ok(procedure) = socket.recv().await {
+            match procedure {
+                Procedure::Call(req, res, _) => {
+                    let _ = res.send(req.data()).await;
+                }
+                Procedure::Notify(req) => {
+                    let _ = socket.notify(req.method(), req.data()).await;
+                }
+                Procedure::StreamCall(..) | Procedure::Heartbeat(_) => {}
+            }
+        }
+        let _ = socket.close(1000, "").await;
+    })
+}
+
+fn main() -> io::Result<()> {
+    actix_web::rt::System::new().block_on(async {
+        println!("listening on ws://127.0.0.1:3000/socket");
+        HttpServer::new(|| App::new().route("/socket", web::get().to(ws_handler)))
+            .bind("127.0.0.1:3000")?
+            .run()
+            .await
+    })
+}
@@ -0,0 +1,243 @@
+#![warn(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+use actix_web::{
+    dev::Payload,
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Method, StatusCode,
+    },
+    web::Bytes,
+    FromRequest, HttpRequest, HttpResponse,
+};
+use futures_util::StreamExt;
+use std::{
+    future::{ready, Future, Ready},
+    io,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+pub use web_socket_io::*;
+
+/// Size of the in-memory pipe between actix and the `SocketIo` instance.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Extractor for establishing `SocketIo` connections.
+///
+/// ### Example
+///
+/// ```rust
+/// async fn ws(upgrade: SocketIoUpgrade) -> HttpResponse {
+///     upgrade.on_upgrade(16, |mut socket| async move {
+///         while let Ok(procedure) = socket.recv().await { ... }
+///     })
+/// }
+///
+/// App::new().route("/socket", web::get().to(ws))
+/// ```
+pub struct SocketIoUpgrade {
+    sec_websocket_key: HeaderValue,
+    protocol: &'static str,
+    headers: HeaderMap,
+    payload: Payload,
+}
+
+impl SocketIoUpgrade {
+    /// Returns the negotiated subprotocol, [`PROTOCOL_V3`], [`PROTOCOL_V2`] or [`PROTOCOL_V1`].
+    pub fn protocol(&self) -> &'static str {
+        self.protocol
+    }
+
+    /// Returns the headers of the upgrade request, e.g. to authenticate the client
+    /// (via a cookie or the `Authorization` header) before accepting it.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let session = authenticate(upgrade.headers().get("authorization"))?;
+    /// upgrade.on_upgrade_with_state(16, session, |mut socket| async move { ... })
+    /// ```
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Finalize upgrading the connection and call the provided callback with `SocketIo` instance.
+    ///
+    /// ## Arguments
+    ///
+    /// * `buffer` - The size of the buffer to be used in the `SocketIo` instance.
+    /// * `callback` - A function that will be called with the upgraded `SocketIo` instance.
+    pub fn on_upgrade<C, Fut>(self, buffer: usize, callback: C) -> HttpResponse
+    where
+        C: FnOnce(SocketIo) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.on_upgrade_with_config(SocketIoConfig::new(buffer), callback)
+    }
+
+    /// Like [`SocketIoUpgrade::on_upgrade`], but the `SocketIo` instance carries `state`,
+    /// e.g. the session authenticated while handling the upgrade request.
+    ///
+    /// See [`SocketIo::with_state`].
+    pub fn on_upgrade_with_state<S, C, Fut>(
+        self,
+        buffer: usize,
+        state: S,
+        callback: C,
+    ) -> HttpResponse
+    where
+        S: 'static,
+        C: FnOnce(SocketIo<S>) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.on_upgrade(buffer, move |socket| callback(socket.with_state(state)))
+    }
+
+    /// Like [`SocketIoUpgrade::on_upgrade`], but creates the `SocketIo` instance from `config`.
+    ///
+    /// `config.protocol` is overridden by the negotiated subprotocol, which determines
+    /// the features used with the peer, see [`Features::from_protocol`].
+    pub fn on_upgrade_with_config<C, Fut>(
+        mut self,
+        mut config: SocketIoConfig,
+        callback: C,
+    ) -> HttpResponse
+    where
+        C: FnOnce(SocketIo) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        // Only the features of the negotiated subprotocol can be used with the peer.
+        config.protocol = self.protocol.into();
+        config.wide_method_names = false;
+        config.deadlines = false;
+        config.trace_context = false;
+
+        // actix's payload isn't `Send`, so it's bridged to `SocketIo` through an in-memory pipe.
+        let (io, pipe) = tokio::io::duplex(PIPE_SIZE);
+        let (pipe_reader, mut pipe_writer) = tokio::io::split(pipe);
+
+        actix_web::rt::spawn(async move {
+            while let Some(Ok(chunk)) = self.payload.next().await {
+                if pipe_writer.write_all(&chunk).await.is_err() {
+                    break;
+                }
+            }
+            let _ = pipe_writer.shutdown().await;
+        });
+
+        let (reader, writer) = tokio::io::split(io);
+        actix_web::rt::spawn(callback(SocketIo::from_config(reader, writer, config)));
+
+        let body = futures_util::stream::unfold(Some(pipe_reader), |reader| async move {
+            let mut reader = reader?;
+            let mut buf = vec![0; 8 * 1024];
+            match reader.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(len) => {
+                    buf.truncate(len);
+                    Some((Ok(Bytes::from(buf)), Some(reader)))
+                }
+                Err(err) => Some((Err::<Bytes, io::Error>(err), None)),
+            }
+        });
+
+        HttpResponse::build(StatusCode::SWITCHING_PROTOCOLS)
+            .upgrade("websocket")
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, self.protocol))
+            .insert_header((
+                header::SEC_WEBSOCKET_ACCEPT,
+                handshake::sign(self.sec_websocket_key.as_bytes()),
+            ))
+            .streaming(body)
+    }
+}
+
+impl FromRequest for SocketIoUpgrade {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        ready(
+            validate(req.method(), req.headers())
+                .map(|(sec_websocket_key, protocol)| Self {
+                    sec_websocket_key,
+                    protocol,
+                    headers: req.headers().clone(),
+                    payload: payload.take(),
+                })
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("invalid websocket upgrade")),
+        )
+    }
+}
+
+/// Returns the `Sec-WebSocket-Key` and the negotiated subprotocol of a valid upgrade request.
+fn validate(method: &Method, headers: &HeaderMap) -> Option<(HeaderValue, &'static str)> {
+    if method != Method::GET {
+        return None;
+    }
+    if !handshake::header_contains(headers.get(header::CONNECTION), "upgrade") {
+        return None;
+    }
+    if !handshake::header_eq(headers.get(header::UPGRADE), "websocket") {
+        return None;
+    }
+    if !handshake::header_eq(headers.get(header::SEC_WEBSOCKET_VERSION), "13") {
+        return None;
+    }
+    let protocol = handshake::negotiate_protocol(headers.get_all(header::SEC_WEBSOCKET_PROTOCOL))?;
+
+    let key = headers
+        .get(header::SEC_WEBSOCKET_KEY)
+        .filter(|key| handshake::is_valid_key(key.as_bytes()))?;
+
+    Some((key.clone(), protocol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderName;
+
+    fn upgrade_headers(protocols: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let mut insert = |name: HeaderName, value: &'static str| {
+            headers.insert(name, HeaderValue::from_static(value));
+        };
+        insert(header::CONNECTION, "keep-alive, Upgrade");
+        insert(header::UPGRADE, "websocket");
+        insert(header::SEC_WEBSOCKET_VERSION, "13");
+        insert(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==");
+        insert(header::SEC_WEBSOCKET_PROTOCOL, protocols);
+        headers
+    }
+
+    #[test]
+    fn validate_negotiates_the_latest_protocol() {
+        let headers = upgrade_headers("websocket.io-rpc-v0.1, websocket.io-rpc-v0.3");
+        let (key, protocol) = validate(&Method::GET, &headers).unwrap();
+        assert_eq!(key, "dGhlIHNhbXBsZSBub25jZQ==");
+        assert_eq!(protocol, PROTOCOL_V3);
+    }
+
+    #[test]
+    fn validate_rejects_invalid_upgrades() {
+        let valid = upgrade_headers(PROTOCOL_V1);
+        assert!(validate(&Method::POST, &valid).is_none());
+        assert!(validate(&Method::GET, &upgrade_headers("chat")).is_none());
+
+        let invalid: [(HeaderName, &str); 4] = [
+            (header::CONNECTION, "keep-alive"),
+            (header::UPGRADE, "h2c"),
+            (header::SEC_WEBSOCKET_VERSION, "8"),
+            (header::SEC_WEBSOCKET_KEY, "short"),
+        ];
+        for (name, value) in invalid {
+            let mut headers = valid.clone();
+            headers.insert(name.clone(), HeaderValue::from_static(value));
+            assert!(
+                validate(&Method::GET, &headers).is_none(),
+                "{name}: {value}"
+            );
+        }
+    }
+}
@@ -3,22 +3,66 @@
 
 use axum::{
     async_trait,
-    body::Bytes,
     extract::FromRequestParts,
-    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, StatusCode},
+    response::IntoResponse,
 };
 use hyper_util::rt::TokioIo;
-use std::future::Future;
+use std::{fmt, future::Future};
 
 pub use web_socket_io::*;
 
 /// Extractor for establishing `SocketIo` connections.
+///
+/// WebSocket extensions offered by the client (e.g. `permessage-deflate`) are declined.
 pub struct SocketIoUpgrade {
     sec_websocket_key: HeaderValue,
+    protocol: &'static str,
+    headers: HeaderMap,
     on_upgrade: hyper::upgrade::OnUpgrade,
 }
 
 impl SocketIoUpgrade {
+    /// Returns the negotiated subprotocol, [`PROTOCOL_V3`], [`PROTOCOL_V2`] or [`PROTOCOL_V1`].
+    pub fn protocol(&self) -> &'static str {
+        self.protocol
+    }
+
+    /// Returns the headers of the upgrade request, e.g. to authenticate the client
+    /// (via a cookie or the `Authorization` header) before accepting it.
+    ///
+    /// The headers are moved out of the request, so extractors that run after
+    /// `SocketIoUpgrade` see none.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let session = authenticate(upgrade.headers().get("authorization"))?;
+    /// upgrade.on_upgrade_with_state(16, session, |mut socket| async move { ... })
+    /// ```
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Selects the first of `supported` (ordered by preference) that the client offered,
+    /// by default the highest version known to this crate is selected.
+    ///
+    /// `supported` should only contain [`PROTOCOL_V3`], [`PROTOCOL_V2`] and/or [`PROTOCOL_V1`].
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// // Client offers `v0.3, v0.2, v0.1`, but this route only speaks `v0.1`.
+    /// let upgrade = upgrade.protocols(&[PROTOCOL_V1])?;
+    /// assert_eq!(upgrade.protocol(), PROTOCOL_V1);
+    /// ```
+    pub fn protocols(mut self, supported: &[&'static str]) -> Result<Self, SocketIoRejection> {
+        let offered = self.headers.get_all(header::SEC_WEBSOCKET_PROTOCOL);
+        self.protocol = handshake::select_protocol(supported, offered)
+            .ok_or(SocketIoRejection::WrongProtocol)?;
+        Ok(self)
+    }
+
     /// Finalize upgrading the connection and call the provided callback with `SocketIo` instance.
     ///
     /// ## Arguments
@@ -30,25 +74,66 @@ impl SocketIoUpgrade {
         C: FnOnce(SocketIo) -> Fut + Send + 'static,
         Fut: Future<Output = ()> + Send + 'static,
     {
+        self.on_upgrade_with_config(SocketIoConfig::new(buffer), callback)
+    }
+
+    /// Like [`SocketIoUpgrade::on_upgrade`], but the `SocketIo` instance carries `state`,
+    /// e.g. the session authenticated while handling the upgrade request.
+    ///
+    /// See [`SocketIo::with_state`].
+    pub fn on_upgrade_with_state<S, C, Fut>(
+        self,
+        buffer: usize,
+        state: S,
+        callback: C,
+    ) -> axum::response::Response
+    where
+        S: Send + 'static,
+        C: FnOnce(SocketIo<S>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_upgrade(buffer, move |socket| callback(socket.with_state(state)))
+    }
+
+    /// Like [`SocketIoUpgrade::on_upgrade`], but creates the `SocketIo` instance from `config`.
+    ///
+    /// `config.protocol` is overridden by the negotiated subprotocol, which determines
+    /// the features used with the peer, see [`Features::from_protocol`].
+    pub fn on_upgrade_with_config<C, Fut>(
+        self,
+        mut config: SocketIoConfig,
+        callback: C,
+    ) -> axum::response::Response
+    where
+        C: FnOnce(SocketIo) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // Only the features of the negotiated subprotocol can be used with the peer.
+        config.protocol = self.protocol.into();
+        config.wide_method_names = false;
+        config.deadlines = false;
+        config.trace_context = false;
         tokio::spawn(async move {
             if let Ok(upgraded) = self.on_upgrade.await {
                 let (reader, writer) = tokio::io::split(TokioIo::new(upgraded));
-                callback(SocketIo::new(reader, writer, buffer)).await;
+                callback(SocketIo::from_config(reader, writer, config)).await;
             }
         });
 
         static H_UPGRADE: HeaderValue = HeaderValue::from_static("upgrade");
         static H_WEBSOCKET: HeaderValue = HeaderValue::from_static("websocket");
-        static H_WS_PROTOCOL: HeaderValue = HeaderValue::from_static("websocket.io-rpc-v0.1");
 
         axum::response::Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
             .header(header::CONNECTION, H_UPGRADE.clone())
             .header(header::UPGRADE, H_WEBSOCKET.clone())
-            .header(header::SEC_WEBSOCKET_PROTOCOL, H_WS_PROTOCOL.clone())
+            .header(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(self.protocol),
+            )
             .header(
                 header::SEC_WEBSOCKET_ACCEPT,
-                sign(self.sec_websocket_key.as_bytes()),
+                handshake::sign(self.sec_websocket_key.as_bytes()),
             )
             .body(axum::body::Body::empty())
             .unwrap()
@@ -60,71 +145,276 @@ impl<S> FromRequestParts<S> for SocketIoUpgrade
 where
     S: Send + Sync,
 {
-    type Rejection = ();
+    type Rejection = SocketIoRejection;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
         if parts.method != Method::GET {
-            return Err(());
+            return Err(SocketIoRejection::MethodNotGet);
         }
-        if !header_contains(&parts.headers, header::CONNECTION, "upgrade") {
-            return Err(());
+        let headers = &parts.headers;
+        if !handshake::header_contains(headers.get(header::CONNECTION), "upgrade") {
+            return Err(SocketIoRejection::MissingUpgrade);
         }
-        if !header_eq(&parts.headers, header::UPGRADE, "websocket") {
-            return Err(());
+        if !handshake::header_eq(headers.get(header::UPGRADE), "websocket") {
+            return Err(SocketIoRejection::MissingUpgrade);
         }
-        if !header_eq(&parts.headers, header::SEC_WEBSOCKET_VERSION, "13") {
-            return Err(());
-        }
-        if !header_eq(
-            &parts.headers,
-            header::SEC_WEBSOCKET_PROTOCOL,
-            "websocket.io-rpc-v0.1",
-        ) {
-            return Err(());
+        if !handshake::header_eq(headers.get(header::SEC_WEBSOCKET_VERSION), "13") {
+            return Err(SocketIoRejection::BadVersion);
         }
+        let protocol =
+            handshake::negotiate_protocol(headers.get_all(header::SEC_WEBSOCKET_PROTOCOL))
+                .ok_or(SocketIoRejection::WrongProtocol)?;
+
+        let sec_websocket_key = headers
+            .get(header::SEC_WEBSOCKET_KEY)
+            .filter(|key| handshake::is_valid_key(key.as_bytes()))
+            .ok_or(SocketIoRejection::MissingKey)?
+            .clone();
+        let on_upgrade = parts
+            .extensions
+            .remove::<hyper::upgrade::OnUpgrade>()
+            .ok_or(SocketIoRejection::NotUpgradable)?;
+
         Ok(Self {
-            sec_websocket_key: parts
-                .headers
-                .get(header::SEC_WEBSOCKET_KEY)
-                .ok_or(())?
-                .clone(),
-
-            on_upgrade: parts
-                .extensions
-                .remove::<hyper::upgrade::OnUpgrade>()
-                .ok_or(())?,
+            protocol,
+            // The request is consumed by the upgrade, so its headers are moved, not cloned.
+            headers: std::mem::take(&mut parts.headers),
+            sec_websocket_key,
+            on_upgrade,
         })
     }
 }
 
-fn sign(key: &[u8]) -> HeaderValue {
-    use base64::engine::Engine as _;
-    use sha1::{Digest, Sha1};
+/// Rejection used for [`SocketIoUpgrade`], describes why the upgrade request is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketIoRejection {
+    /// The request method isn't `GET`.
+    MethodNotGet,
+    /// The `Connection: upgrade` or `Upgrade: websocket` header is missing.
+    MissingUpgrade,
+    /// The `Sec-WebSocket-Version` header isn't `13`.
+    BadVersion,
+    /// The client doesn't offer a supported subprotocol, see [`PROTOCOL_V3`], [`PROTOCOL_V2`] and [`PROTOCOL_V1`].
+    WrongProtocol,
+    /// The `Sec-WebSocket-Key` header is missing or malformed.
+    MissingKey,
+    /// The connection can't be upgraded, e.g. the request was sent over HTTP/2.
+    NotUpgradable,
+}
 
-    let mut sha1 = Sha1::default();
-    sha1.update(key);
-    sha1.update(&b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11"[..]);
-    let b64 = Bytes::from(base64::engine::general_purpose::STANDARD.encode(sha1.finalize()));
-    HeaderValue::from_maybe_shared(b64).expect("base64 is a valid value")
+impl SocketIoRejection {
+    /// Returns the status code of the rejection response.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SocketIoRejection::MethodNotGet => StatusCode::METHOD_NOT_ALLOWED,
+            SocketIoRejection::NotUpgradable => StatusCode::UPGRADE_REQUIRED,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
 }
 
-fn header_eq(headers: &HeaderMap, key: HeaderName, value: &'static str) -> bool {
-    if let Some(header) = headers.get(&key) {
-        header.as_bytes().eq_ignore_ascii_case(value.as_bytes())
-    } else {
-        false
+impl fmt::Display for SocketIoRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketIoRejection::MethodNotGet => write!(f, "request method must be `GET`"),
+            SocketIoRejection::MissingUpgrade => {
+                write!(
+                    f,
+                    "`Connection` and `Upgrade` headers must request a websocket upgrade"
+                )
+            }
+            SocketIoRejection::BadVersion => {
+                write!(f, "`Sec-WebSocket-Version` header must be `13`")
+            }
+            SocketIoRejection::WrongProtocol => write!(
+                f,
+                "`Sec-WebSocket-Protocol` header must offer `{PROTOCOL_V3}`, `{PROTOCOL_V2}` or `{PROTOCOL_V1}`"
+            ),
+            SocketIoRejection::MissingKey => {
+                write!(f, "`Sec-WebSocket-Key` header is missing or invalid")
+            }
+            SocketIoRejection::NotUpgradable => write!(f, "connection isn't upgradable"),
+        }
     }
 }
 
-fn header_contains(headers: &HeaderMap, key: HeaderName, value: &'static str) -> bool {
-    let header = if let Some(header) = headers.get(&key) {
-        header
-    } else {
-        return false;
-    };
-    if let Ok(header) = std::str::from_utf8(header.as_bytes()) {
-        header.to_ascii_lowercase().contains(value)
-    } else {
-        false
+impl std::error::Error for SocketIoRejection {}
+
+impl IntoResponse for SocketIoRejection {
+    fn into_response(self) -> axum::response::Response {
+        (self.status(), self.to_string()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn upgrade_request() -> Parts {
+        let mut req = Request::builder()
+            .uri("/socket")
+            .header(header::CONNECTION, "keep-alive, Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .header(header::SEC_WEBSOCKET_PROTOCOL, PROTOCOL_V3)
+            .body(())
+            .unwrap();
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        req.extensions_mut().insert(on_upgrade);
+        req.into_parts().0
+    }
+
+    async fn extract(mut parts: Parts) -> Result<SocketIoUpgrade, SocketIoRejection> {
+        SocketIoUpgrade::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn declines_permessage_deflate() {
+        let mut parts = upgrade_request();
+        parts.headers.insert(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static("permessage-deflate; client_max_window_bits"),
+        );
+        let upgrade = extract(parts).await.unwrap();
+        let res = upgrade.on_upgrade(16, |_| async {});
+
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert!(!res.headers().contains_key(header::SEC_WEBSOCKET_EXTENSIONS));
+        assert_eq!(
+            res.headers()[header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_a_malformed_key() {
+        for key in [
+            "",
+            "not base64!",
+            "c2hvcnQ=",
+            "dGhlIHNhbXBsZSBub25jZSB0b28gbG9uZw==",
+        ] {
+            let mut parts = upgrade_request();
+            parts
+                .headers
+                .insert(header::SEC_WEBSOCKET_KEY, HeaderValue::from_static(key));
+            let rejection = extract(parts).await.err();
+            assert_eq!(rejection, Some(SocketIoRejection::MissingKey), "{key}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_each_invalid_upgrade() {
+        let mut parts = upgrade_request();
+        parts.method = Method::POST;
+        assert_eq!(
+            extract(parts).await.err(),
+            Some(SocketIoRejection::MethodNotGet)
+        );
+
+        let invalid = [
+            (
+                header::CONNECTION,
+                "keep-alive",
+                SocketIoRejection::MissingUpgrade,
+            ),
+            (header::UPGRADE, "h2c", SocketIoRejection::MissingUpgrade),
+            (
+                header::SEC_WEBSOCKET_VERSION,
+                "8",
+                SocketIoRejection::BadVersion,
+            ),
+            (
+                header::SEC_WEBSOCKET_PROTOCOL,
+                "chat",
+                SocketIoRejection::WrongProtocol,
+            ),
+        ];
+        for (name, value, expected) in invalid {
+            let mut parts = upgrade_request();
+            parts.headers.insert(&name, HeaderValue::from_static(value));
+            assert_eq!(
+                extract(parts).await.err(),
+                Some(expected),
+                "{name}: {value}"
+            );
+        }
+
+        let mut parts = upgrade_request();
+        parts.extensions.clear();
+        let rejection = extract(parts).await.err().unwrap();
+        assert_eq!(rejection, SocketIoRejection::NotUpgradable);
+
+        let res = rejection.into_response();
+        assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(
+            SocketIoRejection::BadVersion.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            SocketIoRejection::MethodNotGet.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+
+    #[tokio::test]
+    async fn routes_restrict_the_negotiated_protocol() {
+        let mut parts = upgrade_request();
+        parts.headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("websocket.io-rpc-v0.2, websocket.io-rpc-v0.1"),
+        );
+        let upgrade = extract(parts).await.unwrap();
+        assert_eq!(upgrade.protocol(), PROTOCOL_V2);
+
+        let upgrade = upgrade.protocols(&[PROTOCOL_V1]).ok().unwrap();
+        assert_eq!(upgrade.protocol(), PROTOCOL_V1);
+        let res = upgrade.on_upgrade(16, |_| async {});
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_PROTOCOL], PROTOCOL_V1);
+
+        let upgrade = extract(upgrade_request()).await.unwrap();
+        let rejection = upgrade.protocols(&[PROTOCOL_V1]).err();
+        assert_eq!(rejection, Some(SocketIoRejection::WrongProtocol));
+    }
+
+    #[tokio::test]
+    async fn headers_are_kept_for_the_callback() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (done, upgraded) = tokio::sync::oneshot::channel();
+        let done = std::sync::Arc::new(std::sync::Mutex::new(Some(done)));
+        let app = axum::Router::new().route(
+            "/socket",
+            axum::routing::get(move |upgrade: SocketIoUpgrade| {
+                let done = done.lock().unwrap().take().unwrap();
+                let headers = upgrade.headers().clone();
+                async move {
+                    upgrade.on_upgrade_with_state(16, headers, |socket| async move {
+                        assert_eq!(socket.state()[header::AUTHORIZATION], "Bearer token");
+                        let _ = done.send(());
+                    })
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET /socket HTTP/1.1\r\nHost: {addr}\r\nConnection: Upgrade\r\n\
+             Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Protocol: {PROTOCOL_V1}\r\nAuthorization: Bearer token\r\n\r\n"
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut head = [0; 12];
+        client.read_exact(&mut head).await.unwrap();
+        assert_eq!(&head, b"HTTP/1.1 101");
+
+        // Dropped without a reply if the assertion in the callback failed.
+        upgraded.await.expect("callback didn't see the headers");
     }
 }
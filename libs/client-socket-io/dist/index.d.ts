@@ -18,6 +18,41 @@ export declare class RPCAbortError extends Error {
     */
     constructor(id: number, name: string, data: string | ArrayLike<number>, reason?: string);
 }
+/**
+ * Represents an error reply (frame type 5) sent by the server for an RPC call.
+ *
+ * @class
+ * @extends {Error}
+ */
+export declare class RPCError extends Error {
+    id: number;
+    data: Uint8Array;
+    /**
+    * Creates an instance of RPCError.
+    *
+    * @param {number} id - The unique identifier of the RPC call that failed.
+    * @param {Uint8Array} data - The error payload sent by the server.
+    */
+    constructor(id: number, data: Uint8Array);
+}
+/**
+ * Rejects an RPC call that was in-flight when the connection closed.
+ *
+ * @class
+ * @extends {RPCError}
+ */
+export declare class ConnectionClosedError extends RPCError {
+    /**
+    * Creates an instance of ConnectionClosedError.
+    *
+    * @param {number} id - The unique identifier of the RPC call that was in-flight.
+    */
+    constructor(id: number);
+}
+/**
+ * Handles an RPC call initiated by the server, see `SocketIo.handle`.
+ */
+export type RPCHandler = (data: Uint8Array) => string | ArrayLike<number> | Promise<string | ArrayLike<number>>;
 export declare class SocketIo {
     #private;
     /**
@@ -54,6 +89,17 @@ export declare class SocketIo {
      * })();
      */
     on(name: string): AsyncGenerator<Uint8Array, Uint8Array | undefined, unknown>;
+    /**
+     * Registers a handler for RPC calls initiated by the server.
+     *
+     * The returned value is sent back as the `Response`. If the handler throws, or no handler
+     * is registered for the method, an `Error` frame is sent instead.
+     * @param {string} name - The method name to handle.
+     * @param {RPCHandler} handler - Called with the request payload.
+     * @example
+     * socket.handle('confirm', data => window.confirm(new TextDecoder().decode(data)) ? 'yes' : 'no');
+     */
+    handle(name: string, handler: RPCHandler): void;
     /**
      * Returns a promise that resolves when the connection is successfully established.
      */
@@ -63,7 +109,9 @@ export declare class SocketIo {
      *
      * @param {string} name - The event name to send.
      * @param {string | ArrayLike<number>} data - The data to send.
-     * @param {{ signal?: AbortSignal }} [opt] - Optional configuration, including an abort signal.
+     * @param {{ signal?: AbortSignal, onchunk?: (chunk: Uint8Array) => void, deadline?: number }} [opt] - Optional configuration,
+     *   including an abort signal, a callback for every chunk of a streamed response, and a deadline
+     *   (in milliseconds) after which the server abandons the call, only sent over `websocket.io-rpc-v0.3`.
      *
      * @example
      * const res = await socket.call('greet', 'hello');
@@ -71,6 +119,8 @@ export declare class SocketIo {
      */
     call(name: string, data: string | ArrayLike<number>, opt?: {
         signal?: AbortSignal;
+        onchunk?: (chunk: Uint8Array) => void;
+        deadline?: number;
     }): Promise<Uint8Array>;
     /**
     * Sends a notification message to the server without waiting for a response.
@@ -80,4 +130,99 @@ export declare class SocketIo {
     * socket.notify('update', 'new data');
     */
     notify(name: string, data: string | ArrayLike<number>): void;
+    /**
+    * Sends an application level heartbeat to the server, surfaced as `Procedure::Heartbeat`.
+    * The server must enable heartbeats, otherwise the frame is rejected.
+    * @param {string | ArrayLike<number>} [data] - The data to send.
+    * @example
+    * setInterval(() => socket.heartbeat(), 30_000);
+    */
+    heartbeat(data?: string | ArrayLike<number>): void;
+}
+/**
+ * Options for `ReconnectingSocketIo`.
+ */
+export interface ReconnectOptions {
+    /** Initial delay (in milliseconds) before re-dialing. Defaults to `500`. */
+    minDelay?: number;
+    /** Maximum delay (in milliseconds) between re-dial attempts. Defaults to `10000`. */
+    maxDelay?: number;
+}
+/**
+ * A `SocketIo` wrapper that automatically re-dials with exponential backoff when the connection drops.
+ *
+ * - Calls and notifications made while disconnected are queued until the connection is re-established.
+ * - Calls that are in-flight when the connection drops are rejected with a `ConnectionClosedError`,
+ *   since the server can't reply to them on a new connection. See `ResumableSocketIo` to re-issue them.
+ * - Event listeners registered with `on` survive reconnects.
+ */
+export declare class ReconnectingSocketIo {
+    #private;
+    url: () => string | URL;
+    opt: ReconnectOptions;
+    /**
+     * The currently active `SocketIo` connection.
+     */
+    socket: SocketIo;
+    /**
+     * Called before every re-dial, with the attempt number (starting at `0`) and the delay in milliseconds.
+     */
+    onreconnecting: ((attempt: number, delay: number) => void) | null;
+    /**
+    * Creates a new ReconnectingSocketIo instance.
+    * @param {() => string | URL} url - Returns the URL to connect to, called on every (re)connect.
+    * @param {ReconnectOptions} [opt] - Backoff configuration.
+    */
+    constructor(url: () => string | URL, opt?: ReconnectOptions);
+    /**
+     * Returns a promise that resolves when the connection is (re-)established.
+     */
+    connect(): Promise<SocketIo>;
+    /**
+     * Listens for the specified event across reconnects.
+     *
+     * Every listener of the same event receives every message.
+     * @param {string} name - The name of the event to listen for.
+     */
+    on(name: string): AsyncGenerator<Uint8Array, Uint8Array | undefined, unknown>;
+    /**
+     * Sends a message to the server and waits for a response, waiting for the connection if needed.
+     * @see SocketIo.call
+     */
+    call(name: string, data: string | ArrayLike<number>, opt?: {
+        signal?: AbortSignal;
+        onchunk?: (chunk: Uint8Array) => void;
+        deadline?: number;
+    }): Promise<Uint8Array>;
+    /**
+     * Sends a notification message to the server, waiting for the connection if needed.
+     * @see SocketIo.notify
+     */
+    notify(name: string, data: string | ArrayLike<number>): Promise<void>;
+    /**
+     * Closes the connection and stops reconnecting.
+     */
+    close(): void;
+}
+/**
+ * A `ReconnectingSocketIo` that re-issues calls which were in-flight when the connection dropped.
+ *
+ * A call is retried on the next connection until it receives a response or an error frame,
+ * so the server may execute it more than once. Only use this for idempotent methods.
+ */
+export declare class ResumableSocketIo extends ReconnectingSocketIo {
+    #private;
+    /**
+     * Sends a message to the server and waits for a response, re-issuing it after a reconnect.
+     * @see SocketIo.call
+     */
+    call(name: string, data: string | ArrayLike<number>, opt?: {
+        signal?: AbortSignal;
+        onchunk?: (chunk: Uint8Array) => void;
+        deadline?: number;
+    }): Promise<Uint8Array>;
+    /**
+     * Closes the connection, stops reconnecting and rejects in-flight calls.
+     */
+    close(): void;
 }
@@ -23,6 +23,43 @@ export class RPCAbortError extends Error {
         this.data = data;
     }
 }
+/**
+ * Represents an error reply (frame type 5) sent by the server for an RPC call.
+ *
+ * @class
+ * @extends {Error}
+ */
+export class RPCError extends Error {
+    id;
+    data;
+    /**
+    * Creates an instance of RPCError.
+    *
+    * @param {number} id - The unique identifier of the RPC call that failed.
+    * @param {Uint8Array} data - The error payload sent by the server.
+    */
+    constructor(id, data) {
+        super(new TextDecoder().decode(data));
+        this.id = id;
+        this.data = data;
+    }
+}
+/**
+ * Rejects an RPC call that was in-flight when the connection closed.
+ *
+ * @class
+ * @extends {RPCError}
+ */
+export class ConnectionClosedError extends RPCError {
+    /**
+    * Creates an instance of ConnectionClosedError.
+    *
+    * @param {number} id - The unique identifier of the RPC call that was in-flight.
+    */
+    constructor(id) {
+        super(id, new TextEncoder().encode("connection closed"));
+    }
+}
 export class SocketIo {
     /**
      * The WebSocket instance used for communication.
@@ -31,31 +68,97 @@ export class SocketIo {
     #next_id = 1;
     #rpc = {};
     #event = {};
+    #handler = {};
     /**
     * Creates a new SocketIo instance.
     * @param {string | URL} url - The URL to connect to via WebSocket.
     */
     constructor(url) {
-        this.ws = new WebSocket(url, "websocket.io-rpc-v0.1");
+        this.ws = new WebSocket(url, ["websocket.io-rpc-v0.3", "websocket.io-rpc-v0.2", "websocket.io-rpc-v0.1"]);
         this.ws.binaryType = "arraybuffer";
         this.ws.onmessage = (ev) => {
-            const data = new Uint8Array(ev.data);
-            const frame_type = data[0];
+            // Frames may also arrive as text messages (e.g. `notify_text`).
+            const data = typeof ev.data == "string" ? new TextEncoder().encode(ev.data) : new Uint8Array(ev.data);
+            const frame_type = data[0] & ~(WIDE_NAME_FLAG | TRACE_FLAG);
+            const wide = (data[0] & WIDE_NAME_FLAG) != 0;
+            // Trace contexts (v0.3) aren't used by the client, skip them.
+            const trace_len = data[0] & TRACE_FLAG ? 1 + data[frame_type == 2 ? 5 : 1] : 0;
             // Notify
             if (frame_type == 1) {
-                const event_name_len = data[1];
-                const event_name = new TextDecoder().decode(data.slice(2, event_name_len + 2));
-                const payload = data.slice(2 + event_name_len);
+                const [event_name, offset] = decodeEventName(data, 1 + trace_len, wide);
+                const payload = data.slice(offset);
                 this.#event[event_name]?.enqueue(payload);
             }
+            // Request
+            else if (frame_type == 2) {
+                const rpc_id = data.slice(1, 5);
+                const [event_name, offset] = decodeEventName(data, 5 + trace_len, wide);
+                const payload = data.slice(offset);
+                this.#reply(rpc_id, event_name, payload);
+            }
             // Response
             else if (frame_type == 4) {
                 const rpc_id = new DataView(data.buffer).getUint32(1, false);
                 const payload = data.slice(5);
-                this.#rpc[rpc_id]?.(payload);
+                const rpc = this.#rpc[rpc_id];
+                rpc?.resolve(rpc.chunks.length ? concatBytes([...rpc.chunks, payload]) : payload);
                 delete this.#rpc[rpc_id];
             }
+            // Error
+            else if (frame_type == 5) {
+                const rpc_id = new DataView(data.buffer).getUint32(1, false);
+                const payload = data.slice(5);
+                this.#rpc[rpc_id]?.reject(new RPCError(rpc_id, payload));
+                delete this.#rpc[rpc_id];
+            }
+            // Chunk
+            else if (frame_type == 6) {
+                const rpc_id = new DataView(data.buffer).getUint32(1, false);
+                const payload = data.slice(5);
+                const rpc = this.#rpc[rpc_id];
+                rpc?.chunks.push(payload);
+                rpc?.onchunk?.(payload);
+            }
         };
+        this.ws.addEventListener("close", () => {
+            // Pending calls will never receive a response on this connection.
+            for (const id in this.#rpc) {
+                this.#rpc[id].reject(new ConnectionClosedError(+id));
+            }
+            this.#rpc = {};
+            for (const name in this.#event) {
+                this.#event[name].close();
+            }
+            this.#event = {};
+        });
+    }
+    /** Whether method names longer than 255 bytes were negotiated. */
+    #wide() {
+        return this.ws.protocol == "websocket.io-rpc-v0.2" || this.#deadlines();
+    }
+
+    /** Whether request deadlines were negotiated. */
+    #deadlines() {
+        return this.ws.protocol == "websocket.io-rpc-v0.3";
+    }
+    async #reply(rpc_id, name, payload) {
+        let frame_type = 4, data;
+        try {
+            const handler = this.#handler[name];
+            if (!handler) {
+                throw new Error(`unknown method: '${name}'`);
+            }
+            data = await handler(payload);
+        }
+        catch (err) {
+            frame_type = 5;
+            data = String(err instanceof Error ? err.message : err);
+        }
+        this.ws.send(concatBytes([
+            [frame_type], // frame type (1 byte)
+            rpc_id,
+            typeof data == "string" ? new TextEncoder().encode(data) : data
+        ]));
     }
     /**
      * Retrieves the current connection status.
@@ -99,6 +202,19 @@ export class SocketIo {
             yield value;
         }
     }
+    /**
+     * Registers a handler for RPC calls initiated by the server.
+     *
+     * The returned value is sent back as the `Response`. If the handler throws, or no handler
+     * is registered for the method, an `Error` frame is sent instead.
+     * @param {string} name - The method name to handle.
+     * @param {RPCHandler} handler - Called with the request payload.
+     * @example
+     * socket.handle('confirm', data => window.confirm(new TextDecoder().decode(data)) ? 'yes' : 'no');
+     */
+    handle(name, handler) {
+        this.#handler[name] = handler;
+    }
     /**
      * Returns a promise that resolves when the connection is successfully established.
      */
@@ -119,14 +235,17 @@ export class SocketIo {
      *
      * @param {string} name - The event name to send.
      * @param {string | ArrayLike<number>} data - The data to send.
-     * @param {{ signal?: AbortSignal }} [opt] - Optional configuration, including an abort signal.
+     * @param {{ signal?: AbortSignal, onchunk?: (chunk: Uint8Array) => void, deadline?: number }} [opt] - Optional configuration,
+     *   including an abort signal, a callback for every chunk of a streamed response, and a deadline
+     *   (in milliseconds) after which the server abandons the call, only sent over `websocket.io-rpc-v0.3`.
      *
      * @example
      * const res = await socket.call('greet', 'hello');
      * console.log(new TextDecoder().decode(res)); // Server's response
      */
     async call(name, data, opt) {
-        const event_name = encodeEventName(name);
+        const [flag, event_name] = encodeEventName(name, this.#wide());
+        const [deadline_flag, deadline] = encodeDeadline(opt?.deadline, this.#deadlines());
         const id = this.#next_id++;
         const rpc_id = new Uint8Array(4);
         new DataView(rpc_id.buffer).setUint32(0, id, false);
@@ -141,12 +260,12 @@ export class SocketIo {
                 delete this.#rpc[id];
             };
         }
-        this.#rpc[id] = resolve;
+        this.#rpc[id] = { resolve, reject, chunks: [], onchunk: opt?.onchunk };
         this.ws.send(concatBytes([
-            [2], // frame type (1 byte)
+            [2 | flag | deadline_flag], // frame type (1 byte)
             rpc_id,
-            [event_name.length], // method name length (1 byte)
-            event_name, // method name (utf8 bytes)
+            deadline, // deadline in milliseconds (0 or 4 bytes)
+            event_name, // method name length (1 or 2 bytes) and name (utf8 bytes)
             typeof data == "string" ? new TextEncoder().encode(data) : data
         ]));
         const response = await promise;
@@ -163,23 +282,218 @@ export class SocketIo {
     * socket.notify('update', 'new data');
     */
     notify(name, data) {
-        const event_name = encodeEventName(name);
+        const [flag, event_name] = encodeEventName(name, this.#wide());
         this.ws.send(concatBytes([
-            [
-                1, // frame type (1 byte)
-                event_name.length // method name length (1 byte)
-            ],
-            event_name, // method name (utf8 bytes)
+            [1 | flag], // frame type (1 byte)
+            event_name, // method name length (1 or 2 bytes) and name (utf8 bytes)
+            typeof data == "string" ? new TextEncoder().encode(data) : data
+        ]));
+    }
+    /**
+    * Sends an application level heartbeat to the server, surfaced as `Procedure::Heartbeat`.
+    * The server must enable heartbeats, otherwise the frame is rejected.
+    * @param {string | ArrayLike<number>} [data] - The data to send.
+    * @example
+    * setInterval(() => socket.heartbeat(), 30_000);
+    */
+    heartbeat(data = []) {
+        this.ws.send(concatBytes([
+            [7], // frame type (1 byte)
             typeof data == "string" ? new TextEncoder().encode(data) : data
         ]));
     }
 }
-function encodeEventName(ev) {
+/**
+ * A `SocketIo` wrapper that automatically re-dials with exponential backoff when the connection drops.
+ *
+ * - Calls and notifications made while disconnected are queued until the connection is re-established.
+ * - Calls that are in-flight when the connection drops are rejected with a `ConnectionClosedError`,
+ *   since the server can't reply to them on a new connection. See `ResumableSocketIo` to re-issue them.
+ * - Event listeners registered with `on` survive reconnects.
+ */
+export class ReconnectingSocketIo {
+    url;
+    opt;
+    /**
+     * The currently active `SocketIo` connection.
+     */
+    socket;
+    /**
+     * Called before every re-dial, with the attempt number (starting at `0`) and the delay in milliseconds.
+     */
+    onreconnecting = null;
+    #ready;
+    #resolve;
+    #connected = false;
+    #attempt = 0;
+    #closed = false;
+    #event = {};
+    /**
+    * Creates a new ReconnectingSocketIo instance.
+    * @param {() => string | URL} url - Returns the URL to connect to, called on every (re)connect.
+    * @param {ReconnectOptions} [opt] - Backoff configuration.
+    */
+    constructor(url, opt = {}) {
+        this.url = url;
+        this.opt = opt;
+        ({ promise: this.#ready, resolve: this.#resolve } = Promise.withResolvers());
+        this.#dial();
+    }
+    #dial() {
+        const socket = new SocketIo(this.url());
+        this.socket = socket;
+        socket.connect().then(() => {
+            this.#attempt = 0;
+            this.#connected = true;
+            for (const name in this.#event) {
+                this.#forward(socket, name);
+            }
+            this.#resolve(socket);
+        }, () => { });
+        socket.ws.addEventListener("close", () => {
+            if (this.#connected) {
+                this.#connected = false;
+                ({ promise: this.#ready, resolve: this.#resolve } = Promise.withResolvers());
+            }
+            if (this.#closed)
+                return;
+            const attempt = this.#attempt++;
+            const delay = Math.min((this.opt.minDelay ?? 500) * 2 ** attempt, this.opt.maxDelay ?? 10000);
+            this.onreconnecting?.(attempt, delay);
+            setTimeout(() => this.#closed || this.#dial(), delay);
+        });
+    }
+    async #forward(socket, name) {
+        for await (const data of socket.on(name)) {
+            for (const c of this.#event[name] ?? []) {
+                c.enqueue(data);
+            }
+        }
+    }
+    /**
+     * Returns a promise that resolves when the connection is (re-)established.
+     */
+    async connect() {
+        return await this.#ready;
+    }
+    /**
+     * Listens for the specified event across reconnects.
+     *
+     * Every listener of the same event receives every message.
+     * @param {string} name - The name of the event to listen for.
+     */
+    async *on(name) {
+        let controller;
+        const stream = new ReadableStream({
+            start: c => { controller = c; }
+        });
+        let listeners = this.#event[name];
+        if (!listeners) {
+            listeners = this.#event[name] = new Set();
+            if (this.#connected)
+                this.#forward(this.socket, name);
+        }
+        listeners.add(controller);
+        const reader = stream.getReader();
+        try {
+            while (true) {
+                const { done, value } = await reader.read();
+                if (done)
+                    return value;
+                yield value;
+            }
+        }
+        finally {
+            listeners.delete(controller);
+        }
+    }
+    /**
+     * Sends a message to the server and waits for a response, waiting for the connection if needed.
+     * @see SocketIo.call
+     */
+    async call(name, data, opt) {
+        const socket = await this.#ready;
+        return await socket.call(name, data, opt);
+    }
+    /**
+     * Sends a notification message to the server, waiting for the connection if needed.
+     * @see SocketIo.notify
+     */
+    async notify(name, data) {
+        const socket = await this.#ready;
+        socket.notify(name, data);
+    }
+    /**
+     * Closes the connection and stops reconnecting.
+     */
+    close() {
+        this.#closed = true;
+        this.socket.ws.close();
+    }
+}
+/**
+ * A `ReconnectingSocketIo` that re-issues calls which were in-flight when the connection dropped.
+ *
+ * A call is retried on the next connection until it receives a response or an error frame,
+ * so the server may execute it more than once. Only use this for idempotent methods.
+ */
+export class ResumableSocketIo extends ReconnectingSocketIo {
+    #closed = false;
+    /**
+     * Sends a message to the server and waits for a response, re-issuing it after a reconnect.
+     * @see SocketIo.call
+     */
+    async call(name, data, opt) {
+        while (true) {
+            try {
+                return await super.call(name, data, opt);
+            }
+            catch (error) {
+                if (!(error instanceof ConnectionClosedError) || this.#closed || opt?.signal?.aborted) {
+                    throw error;
+                }
+            }
+        }
+    }
+    /**
+     * Closes the connection, stops reconnecting and rejects in-flight calls.
+     */
+    close() {
+        this.#closed = true;
+        super.close();
+    }
+}
+/** Set on the op code of frames that carry a trace context. */
+const TRACE_FLAG = 0x80;
+/** Set on the op code of frames whose method name length is a `u16`. */
+const WIDE_NAME_FLAG = 0x40;
+/** Returns the op code flag and the length-prefixed method name. */
+function encodeEventName(ev, wide) {
     const event = new TextEncoder().encode(ev);
-    if (event.byteLength > 255) {
+    if (event.byteLength <= 255) {
+        return [0, concatBytes([[event.byteLength], event])];
+    }
+    if (!wide || event.byteLength > 0xffff) {
         throw new Error(`event name too big: '${ev}'`);
     }
-    return event;
+    return [WIDE_NAME_FLAG, concatBytes([[event.byteLength >> 8, event.byteLength & 0xff], event])];
+}
+/** Set on the op code of `Request` frames that carry a deadline. */
+const DEADLINE_FLAG = 0x20;
+/** Returns the op code flag and the encoded deadline, empty if none is set or deadlines weren't negotiated. */
+function encodeDeadline(deadline, negotiated) {
+    if (deadline == undefined || !negotiated) {
+        return [0, new Uint8Array(0)];
+    }
+    const buf = new Uint8Array(4);
+    new DataView(buf.buffer).setUint32(0, Math.min(Math.max(Math.round(deadline), 0), 0xffffffff), false);
+    return [DEADLINE_FLAG, buf];
+}
+/** Decodes the length-prefixed method name at `offset`, returns it along with the payload offset. */
+function decodeEventName(data, offset, wide) {
+    const len = wide ? (data[offset] << 8) | data[offset + 1] : data[offset];
+    const start = offset + (wide ? 2 : 1);
+    return [new TextDecoder().decode(data.slice(start, start + len)), start + len];
 }
 function concatBytes(chunks) {
     let size = 0, offset = 0;
@@ -0,0 +1,31 @@
+//! Replies to every call with its own payload.
+//!
+//! Run with `cargo run -p warp-socket-io --example echo`, then connect to `ws://127.0.0.1:3000/socket`.
+
+use warp::Filter;
+use warp_socket_io::{socket_io, Procedure, SocketIoUpgrade};
+
+#[tokio::main]
+async fn main() {
+    let routes = warp::path("socket")
+        .and(socket_io())
+        .map(|upgrade: SocketIoUpgrade| {
+            upgrade.on_upgrade(16, |mut socket| async move {
+                while let Ok(procedure) = socket.recv().await {
+                    match procedure {
+                        Procedure::Call(req, res, _) => {
+                            let _ = res.send(req.data()).await;
+                        }
+                        Procedure::Notify(req) => {
+                            let _ = socket.notify(req.method(), req.data()).await;
+                        }
+                        Procedure::StreamCall(..) | Procedure::Heartbeat(_) => {}
+                    }
+                }
+                let _ = socket.close(1000, "").await;
+            })
+        });
+
+    println!("listening on ws://127.0.0.1:3000/socket");
+    warp::serve(routes).run(([127, 0, 0, 1], 3000)).await;
+}
@@ -0,0 +1,345 @@
+#![warn(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncWrite};
+use warp::{
+    http::HeaderMap,
+    reject::{Reject, Rejection},
+    reply::Reply,
+    ws::{Message, Ws},
+    Filter,
+};
+use web_socket_io::web_socket::{DataType, Event, MessageType, Stream, WebSocket};
+
+pub use web_socket_io::*;
+
+/// Size of the in-memory pipe between warp and the `SocketIo` instance.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Creates a filter that validates a `SocketIo` upgrade request.
+///
+/// Rejects with [`UnsupportedProtocol`] if the client doesn't offer a known subprotocol.
+///
+/// ### Limitations
+///
+/// warp only exposes whole messages, so the connection is bridged through an in-memory pipe:
+///
+/// - Fragmented messages from the client are reassembled by warp before they reach the
+///   `SocketIo` instance, so [`Procedure::StreamCall`] is never received and
+///   [`Request::was_streamed`] is always `false`.
+/// - Fragmented replies (e.g. [`Notifier::notify_stream`]) are buffered until their last
+///   fragment is written, then sent to the client as a single message.
+/// - The upgrade request is validated and the key signed by warp's own `ws()` filter,
+///   not by the [`handshake`] module.
+///
+/// ### Example
+///
+/// ```rust
+/// let routes = warp::path("socket")
+///     .and(socket_io())
+///     .map(|upgrade: SocketIoUpgrade| {
+///         upgrade.on_upgrade(16, |mut socket| async move {
+///             while let Ok(procedure) = socket.recv().await { ... }
+///         })
+///     });
+///
+/// warp::serve(routes).run(([127, 0, 0, 1], 3000)).await;
+/// ```
+pub fn socket_io() -> impl Filter<Extract = (SocketIoUpgrade,), Error = Rejection> + Clone {
+    warp::ws().and(warp::header::headers_cloned()).and_then(
+        |ws: Ws, headers: HeaderMap| async move {
+            let protocol = handshake::negotiate_protocol(headers.get_all("sec-websocket-protocol"))
+                .ok_or_else(|| warp::reject::custom(UnsupportedProtocol))?;
+
+            Ok::<_, Rejection>(SocketIoUpgrade {
+                ws,
+                protocol,
+                headers,
+            })
+        },
+    )
+}
+
+/// Rejection of a websocket upgrade that doesn't offer a `SocketIo` subprotocol.
+#[derive(Debug)]
+pub struct UnsupportedProtocol;
+
+impl Reject for UnsupportedProtocol {}
+
+/// Extracted by the [`socket_io`] filter, and used to finish an upgrade.
+pub struct SocketIoUpgrade {
+    ws: Ws,
+    protocol: &'static str,
+    headers: HeaderMap,
+}
+
+impl SocketIoUpgrade {
+    /// Returns the negotiated subprotocol, [`PROTOCOL_V3`], [`PROTOCOL_V2`] or [`PROTOCOL_V1`].
+    pub fn protocol(&self) -> &'static str {
+        self.protocol
+    }
+
+    /// Returns the headers of the upgrade request, e.g. to authenticate the client
+    /// (via a cookie or the `Authorization` header) before accepting it.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let session = authenticate(upgrade.headers().get("authorization"))?;
+    /// upgrade.on_upgrade_with_state(16, session, |mut socket| async move { ... })
+    /// ```
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Finalize upgrading the connection and call the provided callback with `SocketIo` instance.
+    ///
+    /// ## Arguments
+    ///
+    /// * `buffer` - The size of the buffer to be used in the `SocketIo` instance.
+    /// * `callback` - A function that will be called with the upgraded `SocketIo` instance.
+    pub fn on_upgrade<C, Fut>(self, buffer: usize, callback: C) -> impl Reply
+    where
+        C: FnOnce(SocketIo) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_upgrade_with_config(SocketIoConfig::new(buffer), callback)
+    }
+
+    /// Like [`SocketIoUpgrade::on_upgrade`], but the `SocketIo` instance carries `state`,
+    /// e.g. the session authenticated while handling the upgrade request.
+    ///
+    /// See [`SocketIo::with_state`].
+    pub fn on_upgrade_with_state<S, C, Fut>(
+        self,
+        buffer: usize,
+        state: S,
+        callback: C,
+    ) -> impl Reply
+    where
+        S: Send + 'static,
+        C: FnOnce(SocketIo<S>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_upgrade(buffer, move |socket| callback(socket.with_state(state)))
+    }
+
+    /// Like [`SocketIoUpgrade::on_upgrade`], but creates the `SocketIo` instance from `config`.
+    ///
+    /// `config.protocol` is overridden by the negotiated subprotocol, which determines
+    /// the features used with the peer, see [`Features::from_protocol`].
+    pub fn on_upgrade_with_config<C, Fut>(
+        self,
+        mut config: SocketIoConfig,
+        callback: C,
+    ) -> impl Reply
+    where
+        C: FnOnce(SocketIo) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // Only the features of the negotiated subprotocol can be used with the peer.
+        config.protocol = self.protocol.into();
+        config.wide_method_names = false;
+        config.deadlines = false;
+        config.trace_context = false;
+
+        let reply = self.ws.on_upgrade(move |ws| async move {
+            // warp only exposes decoded messages, so they are re-framed into an in-memory pipe.
+            let (io, pipe) = tokio::io::duplex(PIPE_SIZE);
+            let (pipe_reader, pipe_writer) = tokio::io::split(pipe);
+            let (sink, stream) = ws.split();
+
+            tokio::spawn(forward_inbound(stream, WebSocket::client(pipe_writer)));
+            tokio::spawn(forward_outbound(WebSocket::client(pipe_reader), sink));
+
+            let (reader, writer) = tokio::io::split(io);
+            callback(SocketIo::from_config(reader, writer, config)).await;
+        });
+        warp::reply::with_header(reply, "sec-websocket-protocol", self.protocol)
+    }
+}
+
+/// Writes messages received from the client into the pipe.
+async fn forward_inbound<W>(mut stream: SplitStream<warp::ws::WebSocket>, mut ws: WebSocket<W>)
+where
+    W: Unpin + AsyncWrite,
+{
+    while let Some(Ok(msg)) = stream.next().await {
+        let result = if msg.is_binary() {
+            ws.send(msg.as_bytes()).await
+        } else if let Ok(text) = msg.to_str() {
+            ws.send(text).await
+        } else if msg.is_pong() {
+            ws.send_pong(msg.as_bytes()).await
+        } else if msg.is_close() {
+            let _ = match msg.close_frame() {
+                Some((code, reason)) => ws.close((code, reason)).await,
+                None => ws.close(()).await,
+            };
+            return;
+        } else {
+            // Pings are answered by warp.
+            continue;
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+/// Sends frames written by the `SocketIo` instance to the client.
+async fn forward_outbound<R>(
+    mut ws: WebSocket<R>,
+    mut sink: SplitSink<warp::ws::WebSocket, Message>,
+) where
+    R: Unpin + AsyncRead,
+{
+    let mut fragments = Vec::new();
+    loop {
+        let msg = match ws.recv().await {
+            Ok(Event::Data { ty, data }) => match ty {
+                DataType::Complete(ty) => message(ty, data.into()),
+                DataType::Stream(stream) => {
+                    fragments.extend_from_slice(&data);
+                    match stream {
+                        Stream::End(ty) => message(ty, std::mem::take(&mut fragments)),
+                        _ => continue,
+                    }
+                }
+            },
+            Ok(Event::Ping(data)) => Message::ping(data),
+            Ok(Event::Pong(data)) => Message::pong(data),
+            Ok(Event::Close { code, reason }) => {
+                let _ = sink
+                    .send(Message::close_with(code, String::from(reason)))
+                    .await;
+                break;
+            }
+            Ok(Event::Error(_)) | Err(_) => break,
+        };
+        if sink.send(msg).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn message(ty: MessageType, data: Vec<u8>) -> Message {
+    match ty {
+        MessageType::Text => String::from_utf8(data)
+            .map(Message::text)
+            .unwrap_or_else(|err| Message::binary(err.into_bytes())),
+        MessageType::Binary => Message::binary(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::{http::StatusCode, test::RequestBuilder};
+
+    fn upgrade_request(protocols: &str) -> RequestBuilder {
+        warp::test::request()
+            .header("connection", "keep-alive, Upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header("sec-websocket-protocol", protocols)
+    }
+
+    fn echo() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        socket_io().map(|upgrade: SocketIoUpgrade| {
+            upgrade.on_upgrade(16, |mut socket| async move {
+                while let Ok(procedure) = socket.recv().await {
+                    if let Procedure::Call(req, res, _) = procedure {
+                        let _ = res.send(req.data()).await;
+                    }
+                }
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn upgrade_signs_the_key_and_selects_a_protocol() {
+        let res = upgrade_request("websocket.io-rpc-v0.1, websocket.io-rpc-v0.3")
+            .reply(&echo())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            res.headers()["sec-websocket-accept"],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(res.headers()["sec-websocket-protocol"], PROTOCOL_V3);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_protocols() {
+        let err = upgrade_request("chat").filter(&socket_io()).await.err();
+        assert!(err.unwrap().find::<UnsupportedProtocol>().is_some());
+    }
+
+    #[tokio::test]
+    async fn echoes_calls_over_the_upgraded_connection() {
+        let mut client = warp::test::ws()
+            .header("sec-websocket-protocol", PROTOCOL_V1)
+            .handshake(echo())
+            .await
+            .unwrap();
+
+        // `Request` frame: op code, call id, method name length, method name, payload.
+        client
+            .send(Message::binary(*b"\x02\x00\x00\x00\x07\x04echohi"))
+            .await;
+        let msg = client.recv().await.unwrap();
+        assert_eq!(msg.as_bytes(), b"\x04\x00\x00\x00\x07hi");
+    }
+
+    #[tokio::test]
+    async fn fragmented_notifications_arrive_as_one_message() {
+        let payload = vec![7; STREAM_FRAGMENT_SIZE * 2 + 1];
+        let sent = payload.clone();
+        let route = socket_io().map(move |upgrade: SocketIoUpgrade| {
+            let sent = sent.clone();
+            upgrade.on_upgrade(16, |mut socket| async move {
+                let _ = socket.notifier().notify_stream("blob", &sent[..]).await;
+                while socket.recv().await.is_ok() {}
+            })
+        });
+        let mut client = warp::test::ws()
+            .header("sec-websocket-protocol", PROTOCOL_V1)
+            .handshake(route)
+            .await
+            .unwrap();
+
+        let msg = client.recv().await.unwrap();
+        assert_eq!(&msg.as_bytes()[..6], b"\x01\x04blob");
+        assert!(msg.as_bytes()[6..] == payload[..]);
+    }
+
+    #[tokio::test]
+    async fn headers_are_visible_in_the_callback() {
+        let route = socket_io().map(|upgrade: SocketIoUpgrade| {
+            let auth = upgrade.headers()["authorization"].as_bytes().to_vec();
+            upgrade.on_upgrade_with_state(16, auth, |mut socket| async move {
+                let auth = socket.state().clone();
+                let _ = socket.notify("auth", auth).await;
+                while socket.recv().await.is_ok() {}
+            })
+        });
+        let mut client = warp::test::ws()
+            .header("sec-websocket-protocol", PROTOCOL_V1)
+            .header("authorization", "Bearer token")
+            .handshake(route)
+            .await
+            .unwrap();
+
+        // `Notify` frame: op code, event name length, event name, payload.
+        let msg = client.recv().await.unwrap();
+        assert_eq!(msg.as_bytes(), b"\x01\x04authBearer token");
+    }
+}
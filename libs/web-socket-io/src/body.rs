@@ -0,0 +1,74 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+/// `None` marks the end of the body.
+pub(crate) type BodySender = Sender<Option<Box<[u8]>>>;
+
+/// The payload of a [`Procedure::StreamCall`](crate::Procedure::StreamCall), read incrementally
+/// as the fragments arrive.
+///
+/// Fragments are only received while [`SocketIo::recv`](crate::SocketIo::recv) is being polled,
+/// so the body should be consumed on a separate task.
+///
+/// Reading returns `io::ErrorKind::UnexpectedEof` if the connection fails before the
+/// last fragment is received.
+pub struct Body {
+    rx: Receiver<Option<Box<[u8]>>>,
+    chunk: Box<[u8]>,
+    pos: usize,
+    is_done: bool,
+}
+
+impl Body {
+    pub(crate) fn channel(buffer: usize) -> (BodySender, Self) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let body = Self {
+            rx,
+            chunk: Box::default(),
+            pos: 0,
+            is_done: false,
+        };
+        (tx, body)
+    }
+}
+
+impl AsyncRead for Body {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if this.pos < this.chunk.len() {
+                let len = buf.remaining().min(this.chunk.len() - this.pos);
+                buf.put_slice(&this.chunk[this.pos..this.pos + len]);
+                this.pos += len;
+                return Poll::Ready(Ok(()));
+            }
+            if this.is_done {
+                return Poll::Ready(Ok(()));
+            }
+            match ready!(this.rx.poll_recv(cx)) {
+                Some(Some(chunk)) => {
+                    this.chunk = chunk;
+                    this.pos = 0;
+                }
+                Some(None) => this.is_done = true,
+                None => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "stream call was interrupted",
+                    )))
+                }
+            }
+        }
+    }
+}
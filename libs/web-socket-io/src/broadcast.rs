@@ -0,0 +1,102 @@
+use crate::{error::NotifyError, Notifier, SocketIo};
+use futures_util::{stream, StreamExt};
+use std::{collections::HashMap, hash::Hash};
+
+/// `Broadcast` is a group of connections (e.g. a chat room) that receive the same notifications.
+pub struct Broadcast<K> {
+    members: HashMap<K, Notifier>,
+}
+
+impl<K> Default for Broadcast<K> {
+    fn default() -> Self {
+        Self {
+            members: HashMap::new(),
+        }
+    }
+}
+
+impl<K> Broadcast<K>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+{
+    /// Creates an empty `Broadcast` group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a member, returns the previous `Notifier` of that key, if any.
+    pub fn insert(&mut self, key: K, notifier: Notifier) -> Option<Notifier> {
+        self.members.insert(key, notifier)
+    }
+
+    /// Removes a member.
+    pub fn remove(&mut self, key: &K) -> Option<Notifier> {
+        self.members.remove(key)
+    }
+
+    /// Returns the number of members.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns `true` if there are no members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Sends a notification to every member, one after another.
+    ///
+    /// Members whose connection is closed are removed, and their keys are returned.
+    /// Event names longer than 255 bytes are only sent to members that negotiated
+    /// [`PROTOCOL_V2`](crate::PROTOCOL_V2) or later, the others are skipped.
+    pub async fn broadcast(
+        &mut self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> Result<Vec<K>, NotifyError> {
+        let msg = SocketIo::prepare_notify(name, data)?;
+        let mut closed = Vec::new();
+        for (key, notifier) in &self.members {
+            if let Err(NotifyError::ReceiverClosed) = notifier.send_prepared(&msg).await {
+                closed.push(key.clone());
+            }
+        }
+        for key in &closed {
+            self.members.remove(key);
+        }
+        Ok(closed)
+    }
+
+    /// Sends a notification to every member, with up to `concurrency` sends in flight at once.
+    ///
+    /// Unlike [`Broadcast::broadcast`], a slow member only holds up one of the `concurrency` slots,
+    /// instead of the whole fan-out. The sends run on the caller's task, no task is spawned.
+    ///
+    /// Members whose connection is closed are removed, and their keys are returned.
+    pub async fn broadcast_concurrent(
+        &mut self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+        concurrency: usize,
+    ) -> Result<Vec<K>, NotifyError> {
+        let msg = SocketIo::prepare_notify(name, data)?;
+        // Collected first, so the returned future stays `Send` (no closure in the stream type).
+        let sends: Vec<_> = self
+            .members
+            .iter()
+            .map(|(key, notifier)| async {
+                let result = notifier.send_prepared(&msg).await;
+                matches!(result, Err(NotifyError::ReceiverClosed)).then(|| key.clone())
+            })
+            .collect();
+        let closed: Vec<K> = stream::iter(sends)
+            .buffer_unordered(concurrency.max(1))
+            .filter_map(|key| async { key })
+            .collect()
+            .await;
+        for key in &closed {
+            self.members.remove(key);
+        }
+        Ok(closed)
+    }
+}
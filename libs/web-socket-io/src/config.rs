@@ -0,0 +1,248 @@
+use crate::{Metrics, SocketIo, PROTOCOL_V1};
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Options of a [`SocketIo`] instance, see [`SocketIo::from_config`].
+///
+/// ### Example
+///
+/// ```rust
+/// let mut config = SocketIoConfig::new(16);
+/// config.keepalive = Some(Duration::from_secs(30));
+/// let socket = SocketIo::from_config(reader, writer, config);
+/// ```
+///
+/// A config can be cloned, to create many sockets from one template.
+#[derive(Clone)]
+pub struct SocketIoConfig {
+    /// The size of the buffer for the reply channel.
+    pub channel_buffer: usize,
+    /// Maximum payload size of an inbound message, see [`SocketIoBuilder::max_payload_size`].
+    pub max_payload_size: Option<usize>,
+    /// Maximum payload size of an inbound WebSocket frame, see [`SocketIoBuilder::max_frame_size`].
+    pub max_frame_size: Option<usize>,
+    /// Sends a ping every `keepalive` interval, see [`SocketIoBuilder::keepalive`].
+    pub keepalive: Option<Duration>,
+    /// See [`SocketIoBuilder::read_timeout`].
+    pub read_timeout: Option<Duration>,
+    /// See [`SocketIoBuilder::write_timeout`].
+    pub write_timeout: Option<Duration>,
+    /// See [`SocketIoBuilder::wide_method_names`].
+    pub wide_method_names: bool,
+    /// See [`SocketIoBuilder::binary_method_names`].
+    pub binary_method_names: bool,
+    /// The negotiated WebSocket subprotocol, see [`SocketIo::protocol`].
+    pub protocol: Box<str>,
+    /// See [`SocketIoBuilder::metrics`].
+    pub metrics: Option<Arc<dyn Metrics>>,
+    /// See [`SocketIoBuilder::max_in_flight_calls`].
+    pub max_in_flight_calls: Option<usize>,
+    /// See [`SocketIoBuilder::heartbeats`].
+    pub heartbeats: bool,
+    /// See [`SocketIoBuilder::deadlines`].
+    pub deadlines: bool,
+    /// See [`SocketIoBuilder::trace_context`].
+    pub trace_context: bool,
+}
+
+impl SocketIoConfig {
+    /// Creates a config with the given channel buffer size, other options are disabled.
+    pub fn new(channel_buffer: usize) -> Self {
+        Self {
+            channel_buffer,
+            max_payload_size: None,
+            max_frame_size: None,
+            keepalive: None,
+            read_timeout: None,
+            write_timeout: None,
+            wide_method_names: false,
+            binary_method_names: false,
+            protocol: PROTOCOL_V1.into(),
+            metrics: None,
+            max_in_flight_calls: None,
+            heartbeats: false,
+            deadlines: false,
+            trace_context: false,
+        }
+    }
+}
+
+impl Default for SocketIoConfig {
+    /// Same as `SocketIoConfig::new(16)`, the payload size is bounded by the `web-socket` default.
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl fmt::Debug for SocketIoConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocketIoConfig")
+            .field("channel_buffer", &self.channel_buffer)
+            .field("max_payload_size", &self.max_payload_size)
+            .field("max_frame_size", &self.max_frame_size)
+            .field("keepalive", &self.keepalive)
+            .field("read_timeout", &self.read_timeout)
+            .field("write_timeout", &self.write_timeout)
+            .field("wide_method_names", &self.wide_method_names)
+            .field("binary_method_names", &self.binary_method_names)
+            .field("protocol", &self.protocol)
+            .field("metrics", &self.metrics.is_some())
+            .field("max_in_flight_calls", &self.max_in_flight_calls)
+            .field("heartbeats", &self.heartbeats)
+            .field("deadlines", &self.deadlines)
+            .field("trace_context", &self.trace_context)
+            .finish()
+    }
+}
+
+/// Builder for configuring a [`SocketIo`] instance.
+///
+/// ### Example
+///
+/// ```rust
+/// let socket = SocketIoBuilder::new(16)
+///     .write_timeout(Duration::from_secs(10))
+///     .build(reader, writer);
+/// ```
+pub struct SocketIoBuilder {
+    config: SocketIoConfig,
+}
+
+impl SocketIoBuilder {
+    /// Creates a new builder, `buffer` is the size of the buffer for the channel.
+    pub fn new(buffer: usize) -> Self {
+        Self {
+            config: SocketIoConfig::new(buffer),
+        }
+    }
+
+    /// Bounds how long the connection may stay idle.
+    ///
+    /// The timer restarts whenever a frame (including pings and pongs) arrives, it doesn't
+    /// bound a single [`SocketIo::recv`] call. If no frame arrives in time, `recv` returns
+    /// `io::ErrorKind::TimedOut`, every in-flight rpc is reset, and the connection is closed
+    /// with code `1008`. The error is fatal (see [`is_fatal`](crate::error::is_fatal)), so
+    /// [`SocketIo::serve`] returns it instead of waiting for the next frame.
+    /// Combine with [`SocketIoBuilder::keepalive`] to detect dead peers.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds how long each write of a frame (and each flush) to the underlying stream may take.
+    ///
+    /// Waiting for replies to send, e.g. the next fragment of
+    /// [`Notifier::notify_stream`](crate::Notifier::notify_stream), isn't bounded. If a write
+    /// stalls (e.g. a peer that never drains its socket), the writer closes the connection
+    /// with code `1008` and stops sending, further sends fail with `ReceiverClosed`.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sends a ping every `interval`, so that idle connections aren't dropped by proxies.
+    ///
+    /// The pinger stops once the connection is closed. Pongs to these pings are
+    /// used to measure the round-trip time, see [`SocketIo::last_rtt`].
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.config.keepalive = Some(interval);
+        self
+    }
+
+    /// Limits the payload size of an inbound message to `max` bytes.
+    ///
+    /// A fragmented message that grows past the limit is dropped, like an oversized frame.
+    /// Unless [`SocketIoBuilder::max_frame_size`] is set, this also limits single frames.
+    /// The chunks of a response to [`SocketIo::call`] are bound by it too, in total.
+    pub fn max_payload_size(mut self, max: usize) -> Self {
+        self.config.max_payload_size = Some(max);
+        self
+    }
+
+    /// Limits the payload size of an inbound WebSocket frame to `max` bytes.
+    ///
+    /// The limit is checked by `web-socket` against the frame header, so an oversized frame
+    /// fails [`SocketIo::recv`](crate::SocketIo::recv) before its payload is allocated.
+    /// `None` keeps the `web-socket` default, or [`SocketIoBuilder::max_payload_size`] if set.
+    pub fn max_frame_size(mut self, max: usize) -> Self {
+        self.config.max_frame_size = Some(max);
+        self
+    }
+
+    /// Limits how many rpc calls of the peer can be in-flight at once.
+    ///
+    /// Calls beyond the limit are answered with a `"too many requests"` error frame
+    /// (see [`Features::ERROR_FRAMES`](crate::Features::ERROR_FRAMES)),
+    /// without being yielded by [`SocketIo::recv`]. Calls already in-flight aren't affected.
+    pub fn max_in_flight_calls(mut self, max: usize) -> Self {
+        self.config.max_in_flight_calls = Some(max);
+        self
+    }
+
+    /// Yields `Heartbeat` frames of the peer as [`Procedure::Heartbeat`](crate::Procedure::Heartbeat),
+    /// instead of rejecting them as invalid.
+    pub fn heartbeats(mut self, enabled: bool) -> Self {
+        self.config.heartbeats = enabled;
+        self
+    }
+
+    /// Allows method names longer than 255 bytes, encoded with a `u16` length prefix.
+    ///
+    /// Implied by the [`PROTOCOL_V2`](crate::PROTOCOL_V2) (or later) subprotocol, see
+    /// [`SocketIoBuilder::protocol`]. Otherwise such frames are rejected as invalid.
+    pub fn wide_method_names(mut self, enabled: bool) -> Self {
+        self.config.wide_method_names = enabled;
+        self
+    }
+
+    /// Accepts `Request` frames that carry a deadline, see [`Request::deadline`](crate::Request::deadline).
+    ///
+    /// Implied by the [`PROTOCOL_V3`](crate::PROTOCOL_V3) subprotocol, see
+    /// [`SocketIoBuilder::protocol`]. Otherwise such frames are rejected as invalid.
+    pub fn deadlines(mut self, enabled: bool) -> Self {
+        self.config.deadlines = enabled;
+        self
+    }
+
+    /// Accepts and allows sending `Notify` and `Request` frames that carry a trace context,
+    /// see [`Request::trace_context`](crate::Request::trace_context).
+    ///
+    /// Implied by the [`PROTOCOL_V3`](crate::PROTOCOL_V3) subprotocol, see
+    /// [`SocketIoBuilder::protocol`]. Otherwise such frames are rejected as invalid.
+    pub fn trace_context(mut self, enabled: bool) -> Self {
+        self.config.trace_context = enabled;
+        self
+    }
+
+    /// Accepts method names that aren't valid UTF-8, e.g. hashed method ids.
+    ///
+    /// Read such names with [`Request::method_bytes`](crate::Request::method_bytes),
+    /// as [`Request::method`](crate::Request::method) returns an empty string for them.
+    pub fn binary_method_names(mut self, enabled: bool) -> Self {
+        self.config.binary_method_names = enabled;
+        self
+    }
+
+    /// Sets the negotiated WebSocket subprotocol, defaults to [`PROTOCOL_V1`].
+    ///
+    /// Enables the features of the subprotocol, see [`Features::from_protocol`](crate::Features::from_protocol).
+    pub fn protocol(mut self, protocol: impl Into<Box<str>>) -> Self {
+        self.config.protocol = protocol.into();
+        self
+    }
+
+    /// Reports received procedures, resets and errors to `metrics`.
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.config.metrics = Some(metrics);
+        self
+    }
+
+    /// Creates the `SocketIo` instance with the specified reader and writer.
+    pub fn build<I, O>(self, reader: I, writer: O) -> SocketIo
+    where
+        I: Unpin + AsyncRead + Send + 'static,
+        O: Unpin + AsyncWrite + Send + 'static,
+    {
+        SocketIo::from_config(reader, writer, self.config)
+    }
+}
@@ -1,4 +1,5 @@
-use std::fmt;
+use crate::Features;
+use std::{fmt, io};
 
 /// Represents a connection closure with a code and reason.
 #[derive(Debug)]
@@ -17,26 +18,282 @@ impl fmt::Display for ConnClose {
 
 impl std::error::Error for ConnClose {}
 
+impl ConnClose {
+    /// Returns the status code of the close event as a [`CloseCode`].
+    pub fn code_enum(&self) -> CloseCode {
+        CloseCode::from(self.code)
+    }
+}
+
+/// Standard WebSocket close status codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    /// `1000`: The purpose for which the connection was established has been fulfilled.
+    Normal,
+    /// `1001`: An endpoint is "going away", such as a server going down or a browser navigating away.
+    GoingAway,
+    /// `1002`: An endpoint is terminating the connection due to a protocol error.
+    ProtocolError,
+    /// `1003`: An endpoint received a type of data it cannot accept.
+    Unsupported,
+    /// `1008`: An endpoint received a message that violates its policy.
+    PolicyViolation,
+    /// `1009`: An endpoint received a message that is too big to process.
+    TooBig,
+    /// `1011`: The server encountered an unexpected condition.
+    Internal,
+    /// Any other status code.
+    Other(u16),
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1000 => CloseCode::Normal,
+            1001 => CloseCode::GoingAway,
+            1002 => CloseCode::ProtocolError,
+            1003 => CloseCode::Unsupported,
+            1008 => CloseCode::PolicyViolation,
+            1009 => CloseCode::TooBig,
+            1011 => CloseCode::Internal,
+            code => CloseCode::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::GoingAway => 1001,
+            CloseCode::ProtocolError => 1002,
+            CloseCode::Unsupported => 1003,
+            CloseCode::PolicyViolation => 1008,
+            CloseCode::TooBig => 1009,
+            CloseCode::Internal => 1011,
+            CloseCode::Other(code) => code,
+        }
+    }
+}
+
+/// Describes why an inbound frame was rejected or dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The frame is malformed: unknown frame type, truncated, or the method name isn't valid UTF-8.
+    Invalid,
+    /// A `Reset`, `Response`, `Error` or `Chunk` frame refers to a call id that isn't in-flight,
+    /// e.g. a late response to a [`SocketIo::call`](crate::SocketIo::call) that was given up on.
+    /// The frame is dropped, and receiving continues as usual.
+    UnknownCallId(u32),
+    /// A `Request` frame reuses the id of a call that is still in-flight.
+    DuplicateCallId(u32),
+    /// The payload exceeds the limit set by [`SocketIo::set_max_payload`](crate::SocketIo::set_max_payload),
+    /// or a fragmented message exceeds [`SocketIoBuilder::max_payload_size`](crate::SocketIoBuilder::max_payload_size).
+    ///
+    /// Also returned (wrapped in an `io::Error`) by [`SocketIo::call`](crate::SocketIo::call),
+    /// once the chunks of its response exceed `max_payload_size`.
+    PayloadTooLarge,
+    /// A `Request` frame exceeds the limit set by
+    /// [`SocketIoBuilder::max_in_flight_calls`](crate::SocketIoBuilder::max_in_flight_calls).
+    TooManyCalls,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::Invalid => write!(f, "malformed frame."),
+            RejectReason::UnknownCallId(id) => write!(f, "unknown call id: {id}."),
+            RejectReason::DuplicateCallId(id) => write!(f, "duplicate call id: {id}."),
+            RejectReason::PayloadTooLarge => write!(f, "payload is too large."),
+            RejectReason::TooManyCalls => write!(f, "too many calls in-flight."),
+        }
+    }
+}
+
+impl std::error::Error for RejectReason {}
+
+/// Describes why an inbound frame couldn't be parsed.
+///
+/// Returned by [`SocketIo::recv`](crate::SocketIo::recv) wrapped in an `io::Error`
+/// of kind `InvalidData`, use [`io::Error::get_ref`] to downcast it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The message is empty.
+    Empty,
+    /// The frame type (including its flags) is unknown, or a flag isn't enabled.
+    UnknownType(u8),
+    /// The frame ends before its 4-byte rpc id.
+    TruncatedId,
+    /// The frame ends before its 4-byte deadline.
+    TruncatedDeadline,
+    /// The frame ends before the length of its trace context, or within it.
+    TruncatedTraceContext,
+    /// The frame ends before the length prefix of its method name.
+    TruncatedMethodLen,
+    /// The frame ends before the method name does.
+    TruncatedMethod,
+    /// The method name isn't valid UTF-8, see
+    /// [`SocketIoBuilder::binary_method_names`](crate::SocketIoBuilder::binary_method_names).
+    InvalidUtf8Method,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Empty => write!(f, "empty frame."),
+            FrameError::UnknownType(ty) => write!(f, "unknown frame type: {ty:#04x}."),
+            FrameError::TruncatedId => write!(f, "frame is truncated before the rpc id."),
+            FrameError::TruncatedDeadline => write!(f, "frame is truncated before the deadline."),
+            FrameError::TruncatedTraceContext => {
+                write!(f, "frame is truncated within the trace context.")
+            }
+            FrameError::TruncatedMethodLen => {
+                write!(f, "frame is truncated before the method name length.")
+            }
+            FrameError::TruncatedMethod => write!(f, "frame is truncated within the method name."),
+            FrameError::InvalidUtf8Method => write!(f, "method name isn't valid utf-8."),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
 /// Errors that can occur during notification.
 #[derive(Debug)]
 pub enum NotifyError {
-    /// The event name exceeds the allowed size (255 bytes).
+    /// The event name exceeds the allowed size (255 bytes, or 65535 bytes with wide method names).
     EventNameTooBig,
+    /// The trace context exceeds the allowed size (255 bytes).
+    TraceContextTooBig,
+    /// The encoded frame isn't valid UTF-8, so it can't be sent as a text frame.
+    /// This happens if the event name is 128 bytes or longer.
+    InvalidText,
+    /// Reading the payload failed, see [`Notifier::notify_stream`](crate::Notifier::notify_stream).
+    Io(io::Error),
     /// The receiver channel has been closed.
     ReceiverClosed,
+    /// The peer didn't negotiate the feature needed to encode the message,
+    /// see [`SocketIo::features`](crate::SocketIo::features).
+    Unsupported(Features),
 }
 
 impl fmt::Display for NotifyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            NotifyError::Io(err) => write!(f, "failed to read payload: {err}"),
             NotifyError::EventNameTooBig => write!(f, "event name exceeds the allowed length."),
+            NotifyError::TraceContextTooBig => {
+                write!(f, "trace context exceeds the allowed length.")
+            }
+            NotifyError::InvalidText => write!(f, "frame isn't valid utf-8 text."),
             NotifyError::ReceiverClosed => write!(f, "receiver is already closed."),
+            NotifyError::Unsupported(feature) => {
+                write!(f, "peer doesn't support the message: {feature:?}.")
+            }
         }
     }
 }
 
 impl std::error::Error for NotifyError {}
 
+/// Converts to `ErrorKind::BrokenPipe` if the receiver is closed, `ErrorKind::Unsupported` if
+/// the peer lacks a feature, and `ErrorKind::InvalidInput` if the notification can't be encoded,
+/// so handlers returning `io::Result` can use `?`.
+impl From<NotifyError> for io::Error {
+    fn from(err: NotifyError) -> Self {
+        let kind = match err {
+            NotifyError::Io(err) => return err,
+            NotifyError::ReceiverClosed => io::ErrorKind::BrokenPipe,
+            NotifyError::Unsupported(_) => io::ErrorKind::Unsupported,
+            NotifyError::EventNameTooBig
+            | NotifyError::TraceContextTooBig
+            | NotifyError::InvalidText => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Errors that can occur during a non-blocking notification,
+/// see [`Notifier::try_notify`](crate::Notifier::try_notify).
+#[derive(Debug)]
+pub enum TryNotifyError {
+    /// The event name exceeds the allowed size.
+    EventNameTooBig,
+    /// The channel is full, the peer isn't keeping up.
+    Full,
+    /// The receiver channel has been closed.
+    ReceiverClosed,
+}
+
+impl fmt::Display for TryNotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryNotifyError::EventNameTooBig => write!(f, "event name exceeds the allowed length."),
+            TryNotifyError::Full => write!(f, "channel is full."),
+            TryNotifyError::ReceiverClosed => write!(f, "receiver is already closed."),
+        }
+    }
+}
+
+impl std::error::Error for TryNotifyError {}
+
+/// Converts to `ErrorKind::WouldBlock` if the channel is full, otherwise like [`NotifyError`].
+impl From<TryNotifyError> for io::Error {
+    fn from(err: TryNotifyError) -> Self {
+        let kind = match err {
+            TryNotifyError::EventNameTooBig => io::ErrorKind::InvalidInput,
+            TryNotifyError::Full => io::ErrorKind::WouldBlock,
+            TryNotifyError::ReceiverClosed => io::ErrorKind::BrokenPipe,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Errors that can occur while replying to a rpc call, see [`Response::send`](crate::Response::send).
+#[derive(Debug)]
+pub enum ResponseError {
+    /// The caller has reset the call, so the reply was discarded.
+    Reset,
+    /// The peer doesn't support the given feature, which the reply needs,
+    /// see [`SocketIo::features`](crate::SocketIo::features).
+    Unsupported(Features),
+    /// The receiver channel has been closed.
+    ReceiverClosed,
+    /// The buffer passed to [`Response::send_bytes`](crate::Response::send_bytes) is shorter
+    /// than [`Response::HEADER_LEN`](crate::Response::HEADER_LEN), so it has no room for the header.
+    MissingHeader,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseError::Reset => write!(f, "call has been reset."),
+            ResponseError::Unsupported(feature) => {
+                write!(f, "peer doesn't support the reply: {feature:?}.")
+            }
+            ResponseError::ReceiverClosed => write!(f, "receiver is already closed."),
+            ResponseError::MissingHeader => write!(f, "buffer has no room for the header."),
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+/// Converts to `ErrorKind::ConnectionAborted` if the call was reset, `ErrorKind::Unsupported`
+/// if the peer doesn't support the reply, `ErrorKind::BrokenPipe` if the receiver is closed,
+/// and `ErrorKind::InvalidInput` if the buffer has no room for the header.
+impl From<ResponseError> for io::Error {
+    fn from(err: ResponseError) -> Self {
+        let kind = match err {
+            ResponseError::Reset => io::ErrorKind::ConnectionAborted,
+            ResponseError::Unsupported(_) => io::ErrorKind::Unsupported,
+            ResponseError::ReceiverClosed => io::ErrorKind::BrokenPipe,
+            ResponseError::MissingHeader => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
 /// Indicates that the receiver half is closed.
 #[derive(Debug)]
 pub struct ReceiverClosed;
@@ -47,3 +304,66 @@ impl fmt::Display for ReceiverClosed {
     }
 }
 impl std::error::Error for ReceiverClosed {}
+
+/// Converts to `ErrorKind::BrokenPipe`.
+impl From<ReceiverClosed> for io::Error {
+    fn from(err: ReceiverClosed) -> Self {
+        io::Error::new(io::ErrorKind::BrokenPipe, err)
+    }
+}
+
+/// An error reply sent by the peer for a rpc call.
+///
+/// Returned (wrapped in an `io::Error`) by [`SocketIo::call`](crate::SocketIo::call),
+/// use [`io::Error::get_ref`] to downcast it.
+#[derive(Debug)]
+pub struct RemoteError(pub Box<[u8]>);
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "remote error: {}", String::from_utf8_lossy(&self.0))
+    }
+}
+impl std::error::Error for RemoteError {}
+
+/// Errors that can occur while sending a JSON encoded payload.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum JsonError {
+    /// The value couldn't be serialized.
+    Serde(serde_json::Error),
+    /// The payload couldn't be sent.
+    Send(NotifyError),
+    /// The reply couldn't be sent, see [`Response::send_json`](crate::Response::send_json).
+    Reply(ResponseError),
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Serde(err) => write!(f, "failed to serialize payload: {err}"),
+            JsonError::Send(err) => err.fmt(f),
+            JsonError::Reply(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for JsonError {}
+
+/// Returns `true` if an error returned by [`SocketIo::recv`](crate::SocketIo::recv) means
+/// the connection is no longer usable.
+///
+/// - `InvalidData`: A malformed frame was received and dropped, the connection itself
+///   and its in-flight calls are still intact, so it's possible to keep calling `recv`.
+/// - `Interrupted`, `WouldBlock`: Transient, the operation can be retried.
+/// - Anything else (`ConnectionReset`, `ConnectionAborted`, `UnexpectedEof`, ...) is fatal,
+///   including `TimedOut`, returned once the connection was idle for longer than
+///   [`SocketIoBuilder::read_timeout`](crate::SocketIoBuilder::read_timeout).
+pub fn is_fatal(err: &io::Error) -> bool {
+    !matches!(
+        err.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    )
+}
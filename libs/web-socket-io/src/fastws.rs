@@ -0,0 +1,109 @@
+use crate::{FrameSource, RecvFrame, SocketIo, SocketIoConfig};
+use fastwebsockets::{OpCode, Role, WebSocketRead};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf};
+use web_socket::{DataType, Event, MessageType, Stream};
+
+/// Adapts the frames read by `fastwebsockets` to [`Event`]s.
+struct FastSource<S> {
+    ws: WebSocketRead<ReadHalf<S>>,
+    /// Type of the fragmented message being read, if any.
+    fragment: Option<MessageType>,
+}
+
+impl<S> FastSource<S> {
+    fn into_event(&mut self, fin: bool, opcode: OpCode, data: Box<[u8]>) -> Event {
+        let ty = match opcode {
+            OpCode::Text => MessageType::Text,
+            OpCode::Binary => MessageType::Binary,
+            OpCode::Continuation => {
+                let Some(ty) = self.fragment else {
+                    return Event::Error("unexpected continuation frame");
+                };
+                let stream = match fin {
+                    true => {
+                        self.fragment = None;
+                        Stream::End(ty)
+                    }
+                    false => Stream::Next(ty),
+                };
+                let ty = DataType::Stream(stream);
+                return Event::Data { ty, data };
+            }
+            OpCode::Close => {
+                let (code, reason) = match *data {
+                    [a, b, ref reason @ ..] => (u16::from_be_bytes([a, b]), reason),
+                    _ => (1005, &[][..]),
+                };
+                let reason = String::from_utf8_lossy(reason).into();
+                return Event::Close { code, reason };
+            }
+            OpCode::Ping => return Event::Ping(data),
+            OpCode::Pong => return Event::Pong(data),
+        };
+        if self.fragment.is_some() {
+            return Event::Error("expected continuation frame");
+        }
+        let ty = match fin {
+            true => DataType::Complete(ty),
+            false => {
+                self.fragment = Some(ty);
+                DataType::Stream(Stream::Start(ty))
+            }
+        };
+        Event::Data { ty, data }
+    }
+}
+
+impl<S> FrameSource for FastSource<S>
+where
+    S: AsyncRead + Send + 'static,
+{
+    fn recv(mut self: Box<Self>) -> RecvFrame {
+        Box::pin(async move {
+            let frame = self
+                .ws
+                .read_frame(&mut |_| async { Ok::<_, io::Error>(()) })
+                .await;
+            let event = match frame {
+                Ok(frame) => {
+                    let data = Vec::from(frame.payload).into_boxed_slice();
+                    Ok(self.into_event(frame.fin, frame.opcode, data))
+                }
+                Err(err) => Err(io::Error::new(io::ErrorKind::ConnectionReset, err)),
+            };
+            (self as Box<dyn FrameSource>, event)
+        })
+    }
+}
+
+impl SocketIo {
+    /// Creates a new `SocketIo` instance from the server end of a `fastwebsockets` connection,
+    /// whose frames are read by `fastwebsockets`, and replies written by `web_socket`.
+    ///
+    /// `frame_socket` must be freshly upgraded, a frame it has already buffered is lost.
+    /// Pings and close frames are answered by the `SocketIo` instance, not by `fastwebsockets`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let (response, upgrade) = fastwebsockets::upgrade::upgrade(&mut req)?;
+    /// tokio::spawn(async move {
+    ///     let socket = SocketIo::from_fastwebsockets(upgrade.await?, 16);
+    ///     ...
+    /// });
+    /// ```
+    pub fn from_fastwebsockets<S>(frame_socket: fastwebsockets::WebSocket<S>, buffer: usize) -> Self
+    where
+        S: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(frame_socket.into_inner());
+        // Only the read half is used by `fastwebsockets`.
+        let (mut ws, _) =
+            fastwebsockets::after_handshake_split(reader, tokio::io::sink(), Role::Server);
+        ws.set_auto_close(false);
+        ws.set_auto_pong(false);
+        let source = FastSource { ws, fragment: None };
+        Self::from_source(source, writer, SocketIoConfig::new(buffer))
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{
+    error::{RejectReason, ResponseError},
+    Request, Response,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::io::{self, Read, Write};
+
+impl Request {
+    /// Decompresses the gzip encoded data payload, up to `max_len` bytes.
+    ///
+    /// A few bytes of gzip can inflate to gigabytes, so the output is bounded on its own,
+    /// regardless of [`SocketIoBuilder::max_payload_size`](crate::SocketIoBuilder::max_payload_size).
+    /// Fails with `ErrorKind::InvalidData` if the payload isn't valid gzip, or inflates to
+    /// more than `max_len` bytes.
+    ///
+    /// This is a payload level encoding, agreed on by the application, not a WebSocket extension.
+    pub fn data_gunzip(&self, max_len: usize) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        GzDecoder::new(self.data())
+            .take(max_len as u64 + 1)
+            .read_to_end(&mut data)?;
+
+        if data.len() > max_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                RejectReason::PayloadTooLarge,
+            ));
+        }
+        Ok(data)
+    }
+}
+
+impl Response {
+    /// Sends the response, with the data compressed by gzip.
+    pub async fn send_gzip(self, data: impl AsRef<[u8]>) -> Result<(), ResponseError> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(data.as_ref())
+            .expect("writing to a `Vec` never fails");
+
+        let data = encoder.finish().expect("writing to a `Vec` never fails");
+        self.send(data).await
+    }
+}
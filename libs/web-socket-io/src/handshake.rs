@@ -0,0 +1,84 @@
+//! Framework-agnostic helpers for validating a WebSocket upgrade request,
+//! shared by the web-framework integrations (e.g. `axum-socket-io`).
+//!
+//! ### Example
+//!
+//! ```rust
+//! let is_upgrade = header_contains(headers.get("connection"), "upgrade")
+//!     && header_eq(headers.get("upgrade"), "websocket")
+//!     && header_eq(headers.get("sec-websocket-version"), "13");
+//!
+//! let protocol = negotiate_protocol(headers.get_all("sec-websocket-protocol"))?;
+//! let key = headers.get("sec-websocket-key").filter(|key| is_valid_key(key))?;
+//! let accept = sign(key);
+//! ```
+
+use crate::{PROTOCOL_V1, PROTOCOL_V2, PROTOCOL_V3};
+use base64::engine::Engine as _;
+use sha1::{Digest, Sha1};
+
+/// Returns the `Sec-WebSocket-Accept` value for the given `Sec-WebSocket-Key`.
+pub fn sign(key: &[u8]) -> String {
+    let mut sha1 = Sha1::default();
+    sha1.update(key);
+    sha1.update(&b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11"[..]);
+    base64::engine::general_purpose::STANDARD.encode(sha1.finalize())
+}
+
+/// Returns `true` if `key` is a valid `Sec-WebSocket-Key`, a base64 encoded 16-byte value.
+pub fn is_valid_key(key: &[u8]) -> bool {
+    let mut decoded = [0; 18];
+    matches!(
+        base64::engine::general_purpose::STANDARD.decode_slice(key, &mut decoded),
+        Ok(16)
+    )
+}
+
+/// Returns `true` if the header is present and equals `value`, ignoring ASCII case.
+pub fn header_eq(header: Option<impl AsRef<[u8]>>, value: &str) -> bool {
+    match header {
+        Some(header) => header.as_ref().eq_ignore_ascii_case(value.as_bytes()),
+        None => false,
+    }
+}
+
+/// Returns `true` if the header is present and contains `value`, ignoring ASCII case.
+///
+/// `value` must be lowercase.
+pub fn header_contains(header: Option<impl AsRef<[u8]>>, value: &str) -> bool {
+    let Some(header) = header else {
+        return false;
+    };
+    match std::str::from_utf8(header.as_ref()) {
+        Ok(header) => header.to_ascii_lowercase().contains(value),
+        Err(_) => false,
+    }
+}
+
+/// Returns the preferred subprotocol, [`PROTOCOL_V3`], [`PROTOCOL_V2`] or [`PROTOCOL_V1`],
+/// offered by the (comma-separated) `Sec-WebSocket-Protocol` header values.
+pub fn negotiate_protocol<I>(headers: I) -> Option<&'static str>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    select_protocol(&[PROTOCOL_V3, PROTOCOL_V2, PROTOCOL_V1], headers)
+}
+
+/// Returns the first of `supported` (ordered by preference), that is offered by
+/// the (comma-separated) `Sec-WebSocket-Protocol` header values.
+pub fn select_protocol<I>(supported: &[&'static str], headers: I) -> Option<&'static str>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let headers: Vec<I::Item> = headers.into_iter().collect();
+    supported.iter().copied().find(|protocol| {
+        headers.iter().any(|header| {
+            header
+                .as_ref()
+                .split(|&b| b == b',')
+                .any(|token| token.trim_ascii().eq_ignore_ascii_case(protocol.as_bytes()))
+        })
+    })
+}
@@ -0,0 +1,29 @@
+use crate::{error::JsonError, Notifier, Request, Response};
+use serde::{de::DeserializeOwned, Serialize};
+
+impl Request {
+    /// Deserializes the data payload as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(self.data())
+    }
+}
+
+impl Response {
+    /// Sends the response, serialized as JSON.
+    pub async fn send_json<T: Serialize + ?Sized>(self, value: &T) -> Result<(), JsonError> {
+        let data = serde_json::to_vec(value).map_err(JsonError::Serde)?;
+        self.send(data).await.map_err(JsonError::Reply)
+    }
+}
+
+impl Notifier {
+    /// Sends a notification with the given name, and data serialized as JSON.
+    pub async fn notify_json<T: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), JsonError> {
+        let data = serde_json::to_vec(value).map_err(JsonError::Serde)?;
+        self.notify(name, data).await.map_err(JsonError::Send)
+    }
+}
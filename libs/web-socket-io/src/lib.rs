@@ -1,28 +1,143 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+mod body;
+mod broadcast;
+mod config;
 /// Error types
 pub mod error;
-use error::{ConnClose, NotifyError, ReceiverClosed};
+#[cfg(feature = "fastwebsockets")]
+mod fastws;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "handshake")]
+pub mod handshake;
+#[cfg(feature = "serde")]
+mod json;
+mod metrics;
+mod router;
+mod sink;
+mod source;
+mod split;
+mod stream;
+#[cfg(any(test, feature = "testing"))]
+mod testing;
+#[cfg(test)]
+mod tests;
+
+pub use body::Body;
+pub use broadcast::Broadcast;
+pub use config::{SocketIoBuilder, SocketIoConfig};
+use error::{
+    ConnClose, FrameError, NotifyError, RejectReason, RemoteError, ResponseError, TryNotifyError,
+};
+pub use metrics::Metrics;
+pub use router::Router;
+pub use sink::{ResponseChunks, ResponseSink};
+use source::FrameReader;
+pub use source::{FrameSource, RecvFrame};
+pub use split::SocketIoReceiver;
+pub use stream::ProcedureStream;
 pub use web_socket;
 
+use atomic_waker::AtomicWaker;
+use bytes::{BufMut, Bytes, BytesMut};
 use std::{
     collections::HashMap,
     future::Future,
     io,
     ops::ControlFlow,
-    sync::{Arc, Mutex},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
     task::{Context, Poll},
+    time::Duration,
 };
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
-    sync::mpsc::Sender,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    sync::{
+        mpsc::{error::TrySendError, Receiver, Sender, WeakSender},
+        oneshot,
+    },
+    task::{AbortHandle, JoinHandle},
+    time::{Instant, Sleep},
 };
-use web_socket::{DataType, Event, Stream, WebSocket};
+use web_socket::{DataType, Event, Frame as WsFrame, Stream, WebSocket};
 
 pub(crate) type DynErr = Box<dyn std::error::Error + Send + Sync>;
 
-type Resetter = Arc<Mutex<HashMap<u32, ResetShared>>>;
+type Resetter = Arc<InFlightCalls>;
+
+/// Calls received from the peer, that haven't been answered yet.
+#[derive(Default)]
+struct InFlightCalls {
+    calls: Mutex<HashMap<u32, ResetShared>>,
+    /// Notified once the last in-flight call is answered, see [`SocketIo::shutdown`].
+    idle: tokio::sync::Notify,
+}
+
+impl InFlightCalls {
+    /// Removes the call `id`, unless the id was reused by a newer call already.
+    fn remove(&self, id: u32, reset: &ResetShared) {
+        let mut calls = lock(&self.calls);
+        if calls.get(&id).is_some_and(|r| Arc::ptr_eq(r, reset)) {
+            calls.remove(&id);
+        }
+        if calls.is_empty() {
+            self.idle.notify_waiters();
+        }
+    }
+}
+type PendingCalls = Arc<Mutex<HashMap<u32, PendingCall>>>;
+
+enum PendingCall {
+    /// Started by [`SocketIo::call`], chunks are concatenated with the final response.
+    Buffered {
+        resolve: oneshot::Sender<io::Result<Vec<u8>>>,
+        /// Payloads of the `Chunk` frames received so far.
+        chunks: Vec<u8>,
+    },
+    /// Started by [`SocketIo::call_chunked`], chunks are yielded as they arrive.
+    Streamed(sink::ChunkSender),
+}
+
+/// Owned by the future returned from [`SocketIo::call`], so that a call that is given up
+/// (e.g. dropped by a timeout) doesn't keep its id, and the peer stops working on it.
+struct CallGuard {
+    id: u32,
+    calls: PendingCalls,
+    tx: Sender<Reply>,
+    /// Whether the `Request` frame was queued, otherwise the peer never saw the call.
+    sent: bool,
+}
+
+impl CallGuard {
+    async fn send(&mut self, frame: Bytes) -> io::Result<()> {
+        self.tx
+            .send(Reply::Response(frame))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        self.sent = true;
+        Ok(())
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        // Already removed once the call was answered, or the connection failed.
+        let pending = lock(&self.calls).remove(&self.id).is_some();
+        if pending && self.sent {
+            let _ = self
+                .tx
+                .try_send(Reply::Response(encode_response(3, self.id, &[])));
+        }
+    }
+}
+
+#[cfg(feature = "reject-hook")]
+type RejectHook = Box<dyn FnMut(RejectReason, &[u8]) + Send>;
 
 /// `SocketIo` manages WebSocket communication for handling RPC events.
 ///
@@ -33,15 +148,53 @@ type Resetter = Arc<Mutex<HashMap<u32, ResetShared>>>;
 /// allowing for better control over ongoing operations.
 ///
 /// The struct efficiently manages concurrent RPC events and notifies clients of relevant occurrences.
-pub struct SocketIo {
-    ws: WebSocket<Box<dyn AsyncRead + Send + Unpin + 'static>>,
+///
+/// `S` is per-connection application state (e.g. an authenticated session), see [`SocketIo::with_state`].
+pub struct SocketIo<S = ()> {
+    ws: FrameReader,
     tx: Sender<Reply>,
+    writer: JoinHandle<()>,
     resetter: Resetter,
+    abandoned: Vec<u32>,
+    payload_limits: HashMap<Box<str>, usize>,
+    stream_buf: Vec<u8>,
+    max_message_len: Option<usize>,
+    is_streaming: bool,
+    stream_calls: bool,
+    stream_body: Option<body::BodySender>,
+    read_timeout: Option<Duration>,
+    max_in_flight_calls: Option<usize>,
+    heartbeats: bool,
+    wide_names: bool,
+    binary_names: bool,
+    protocol: Box<str>,
+    features: Features,
+    id: ConnId,
+    next_call_id: u32,
+    pending_calls: PendingCalls,
+    /// Keepalive pings carry the time they were sent, relative to `epoch`.
+    epoch: Instant,
+    last_rtt: Option<Duration>,
+    byte_stats: Arc<ByteStats>,
+    metrics: Option<Arc<dyn Metrics>>,
+    #[cfg(feature = "reject-hook")]
+    on_rejected_frame: Option<RejectHook>,
+    state: S,
 }
 
 enum Reply {
-    Ping(Box<[u8]>),
-    Response(Box<[u8]>),
+    Ping(Bytes),
+    /// Carries the time the ping was sent, see [`SocketIo::last_rtt`].
+    KeepAlive(Duration),
+    Response(Bytes),
+    /// Frames written back to back, see [`Notifier::notify_all`].
+    Batch(Vec<Bytes>),
+    /// Fragments of a single message, `None` ends the message, see [`Notifier::notify_stream`].
+    Stream(Receiver<Option<Bytes>>),
+    /// Must be valid UTF-8.
+    Text(Bytes),
+    Flushed(Bytes, oneshot::Sender<()>),
+    Close(u16, Box<str>, oneshot::Sender<io::Result<()>>),
 }
 
 /// `Procedure` represents an RPC (Remote Procedure Call) or notification in the system.
@@ -51,224 +204,1954 @@ pub enum Procedure {
 
     /// `Notify` represents a one-way notification that includes only a request.
     Notify(Request),
+
+    /// `StreamCall` represents a RPC event whose payload is streamed through the [`Body`],
+    /// the `Request` carries only the method name.
+    ///
+    /// Only produced when enabled with [`SocketIo::stream_calls`].
+    StreamCall(Request, Body, Response, AbortController),
+
+    /// `Heartbeat` represents an application level heartbeat, carrying its payload.
+    ///
+    /// Only produced when enabled with [`SocketIoBuilder::heartbeats`].
+    Heartbeat(Bytes),
+}
+
+/// Outcome of [`SocketIo::recv_event`].
+pub enum RecvOutcome {
+    /// A rpc or notification was received.
+    Procedure(Procedure),
+    /// The peer closed the connection.
+    Closed(ConnClose),
+    /// Receiving failed, see [`is_fatal`](error::is_fatal) to tell whether the connection is still usable.
+    Errored(io::Error),
+}
+
+impl Procedure {
+    /// Returns `true` if this is a `Call`.
+    #[inline]
+    pub fn is_call(&self) -> bool {
+        matches!(self, Procedure::Call(..))
+    }
+
+    /// Returns `true` if this is a `Notify`.
+    #[inline]
+    pub fn is_notify(&self) -> bool {
+        matches!(self, Procedure::Notify(..))
+    }
+
+    /// Returns the rpc id of a `Call` or `StreamCall`, or `None` if this is a `Notify`.
+    #[inline]
+    pub fn id(&self) -> Option<u32> {
+        match self {
+            Procedure::Call(_, res, _) | Procedure::StreamCall(_, _, res, _) => Some(res.id()),
+            Procedure::Notify(_) | Procedure::Heartbeat(_) => None,
+        }
+    }
+
+    /// Returns the method name of the inner [`Request`], or an empty string for a `Heartbeat`.
+    #[inline]
+    pub fn method(&self) -> &str {
+        match self {
+            Procedure::Call(req, ..) | Procedure::Notify(req) | Procedure::StreamCall(req, ..) => {
+                req.method()
+            }
+            Procedure::Heartbeat(_) => "",
+        }
+    }
+
+    /// Returns the parts of a `Call`, or `None` for any other variant (including `StreamCall`).
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// while let Some((req, res, ctl)) = socket.recv().await?.into_call() { ... }
+    /// ```
+    pub fn into_call(self) -> Option<(Request, Response, AbortController)> {
+        match self {
+            Procedure::Call(req, res, ctl) => Some((req, res, ctl)),
+            _ => None,
+        }
+    }
+
+    /// Returns the request of a `Notify`, or `None` if this is a `Call` or `StreamCall`.
+    pub fn into_notify(self) -> Option<Request> {
+        match self {
+            Procedure::Notify(req) => Some(req),
+            _ => None,
+        }
+    }
+}
+
+/// A pre-encoded notification frame.
+///
+/// Encoding is done once by [`SocketIo::prepare_notify`], then the same frame can be sent
+/// to any number of clients via [`Notifier::send_prepared`] without re-encoding or copying.
+#[derive(Debug, Clone)]
+pub struct PreparedMessage {
+    buf: Bytes,
 }
 
 /// `Notifier` is used to send notifications, Sends notifications where no response expected.
+///
+/// Notifiers compare equal (and hash the same) if they belong to the same connection.
 #[derive(Clone)]
 pub struct Notifier {
+    id: ConnId,
     tx: Sender<Reply>,
+    features: Features,
+    byte_stats: Arc<ByteStats>,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl PartialEq for Notifier {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Notifier {}
+
+impl std::hash::Hash for Notifier {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+/// A `Notifier` that doesn't keep the connection's reply channel alive,
+/// see [`Notifier::downgrade`].
+#[derive(Clone)]
+pub struct WeakNotifier {
+    id: ConnId,
+    tx: WeakSender<Reply>,
+    features: Features,
+    byte_stats: Arc<ByteStats>,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl WeakNotifier {
+    /// Returns the id of the connection this notifier belongs to.
+    pub fn id(&self) -> ConnId {
+        self.id
+    }
+
+    /// Returns a `Notifier`, or `None` if the reply channel is gone, i.e. the `SocketIo`
+    /// and every `Notifier` and `Response` of the connection were dropped.
+    pub fn upgrade(&self) -> Option<Notifier> {
+        Some(Notifier {
+            id: self.id,
+            tx: self.tx.upgrade()?,
+            features: self.features,
+            byte_stats: self.byte_stats.clone(),
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+/// Payload bytes of the data messages read and written by a connection.
+#[derive(Default)]
+struct ByteStats {
+    read: AtomicU64,
+    written: AtomicU64,
+}
+
+impl ByteStats {
+    fn get(&self) -> (u64, u64) {
+        (
+            self.read.load(Ordering::Relaxed),
+            self.written.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Identifies a `SocketIo` connection, unique within the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnId(u64);
+
+impl ConnId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the id as a number.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for ConnId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// WebSocket subprotocol of the original frame encoding.
+pub const PROTOCOL_V1: &str = "websocket.io-rpc-v0.1";
+
+/// WebSocket subprotocol that also allows method names longer than 255 bytes,
+/// see [`SocketIoBuilder::wide_method_names`].
+pub const PROTOCOL_V2: &str = "websocket.io-rpc-v0.2";
+
+/// WebSocket subprotocol that also allows request deadlines and trace contexts,
+/// see [`SocketIoBuilder::deadlines`] and [`SocketIoBuilder::trace_context`].
+pub const PROTOCOL_V3: &str = "websocket.io-rpc-v0.3";
+
+/// Optional wire format features that can be used with the peer, see [`SocketIo::features`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Features(u8);
+
+impl Features {
+    /// No optional features, as negotiated by [`PROTOCOL_V1`].
+    pub const NONE: Self = Self(0);
+    /// Method names longer than 255 bytes, as negotiated by [`PROTOCOL_V2`] and later.
+    pub const WIDE_METHOD_NAMES: Self = Self(1);
+    /// Requests that carry a deadline, as negotiated by [`PROTOCOL_V3`].
+    pub const DEADLINES: Self = Self(2);
+    /// Error replies, see [`Response::send_error`], as negotiated by [`PROTOCOL_V2`] and later.
+    ///
+    /// Without it, calls that fail on their own (e.g. a dropped [`Response`], or a call
+    /// rejected by [`SocketIoBuilder::max_in_flight_calls`]) close the connection instead.
+    pub const ERROR_FRAMES: Self = Self(4);
+    /// Responses sent in chunks, see [`Response::into_sink`], as negotiated by [`PROTOCOL_V2`]
+    /// and later.
+    pub const CHUNKED_RESPONSES: Self = Self(8);
+    /// Notifications and requests that carry a trace context, see [`Request::trace_context`],
+    /// as negotiated by [`PROTOCOL_V3`].
+    pub const TRACE_CONTEXT: Self = Self(16);
+
+    /// Returns the features of the given subprotocol, unknown subprotocols have none.
+    pub fn from_protocol(protocol: &str) -> Self {
+        let v2 = Self::WIDE_METHOD_NAMES | Self::ERROR_FRAMES | Self::CHUNKED_RESPONSES;
+        match protocol {
+            PROTOCOL_V2 => v2,
+            PROTOCOL_V3 => v2 | Self::DEADLINES | Self::TRACE_CONTEXT,
+            _ => Self::NONE,
+        }
+    }
+
+    /// Returns `true` if all the features of `other` are supported.
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the raw bits of the feature set.
+    #[inline]
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for Features {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Size of the fragments sent by [`Notifier::notify_stream`].
+pub const STREAM_FRAGMENT_SIZE: usize = 16 * 1024;
+
+/// Maximum number of queued replies the writer task writes before flushing.
+const WRITE_BATCH: usize = 32;
+
+/// Set on the op code of `Notify` and `Request` frames that carry a trace context.
+const TRACE_FLAG: u8 = 0x80;
+
+/// Set on the op code of `Notify` and `Request` frames whose method name length is a `u16`.
+const WIDE_NAME_FLAG: u8 = 0x40;
+
+/// Set on the op code of `Request` frames that carry a deadline.
+const DEADLINE_FLAG: u8 = 0x20;
+
+/// Returns the op code flag needed to encode the method name.
+fn method_name_flag(name: &[u8], wide_names: bool) -> Result<u8, NotifyError> {
+    match name.len() {
+        0..=255 => Ok(0),
+        len if wide_names && len <= u16::MAX as usize => Ok(WIDE_NAME_FLAG),
+        _ => Err(NotifyError::EventNameTooBig),
+    }
+}
+
+fn put_method_name(buf: &mut Vec<u8>, name: &[u8]) {
+    match u8::try_from(name.len()) {
+        Ok(len) => buf.push(len),
+        Err(_) => buf.extend_from_slice(&(name.len() as u16).to_be_bytes()),
+    }
+    buf.extend_from_slice(name);
+}
+
+fn encode_notify(name: &str, data: &[u8], wide_names: bool) -> Result<Bytes, NotifyError> {
+    encode_traced_notify(&[], name, data, wide_names)
 }
 
-async fn notify(tx: &Sender<Reply>, name: &str, data: &[u8]) -> Result<(), NotifyError> {
+fn encode_traced_notify(
+    trace: &[u8],
+    name: &str,
+    data: &[u8],
+    wide_names: bool,
+) -> Result<Bytes, NotifyError> {
     let event_name = name.as_bytes();
-    let event_name_len: u8 = event_name
-        .len()
-        .try_into()
-        .map_err(|_| NotifyError::EventNameTooBig)?;
+    let flag = method_name_flag(event_name, wide_names)?;
 
-    let mut buf = Vec::with_capacity(5 + data.len());
+    let mut buf = Vec::with_capacity(6 + trace.len() + event_name.len() + data.len());
+
+    buf.push(1 | flag); // frame type
+    put_trace_context(&mut buf, trace)?;
+    put_method_name(&mut buf, event_name);
+    buf.extend_from_slice(data);
+    Ok(buf.into())
+}
+
+fn encode_call(
+    id: u32,
+    trace: &[u8],
+    name: &str,
+    data: &[u8],
+    wide_names: bool,
+) -> Result<Bytes, NotifyError> {
+    let method = name.as_bytes();
+    let flag = method_name_flag(method, wide_names)?;
+
+    let mut buf = Vec::with_capacity(8 + trace.len() + method.len() + data.len());
 
-    buf.push(1); // frame type
-    buf.push(event_name_len);
-    buf.extend_from_slice(event_name);
+    buf.push(2 | flag); // frame type
+    buf.extend_from_slice(&id.to_be_bytes()); // call id
+    put_trace_context(&mut buf, trace)?;
+    put_method_name(&mut buf, method);
     buf.extend_from_slice(data);
+    Ok(buf.into())
+}
+
+/// Appends the trace context (if any) and sets the flag on the op code at `buf[0]`.
+fn put_trace_context(buf: &mut Vec<u8>, trace: &[u8]) -> Result<(), NotifyError> {
+    if trace.is_empty() {
+        return Ok(());
+    }
+    let trace_len: u8 = trace
+        .len()
+        .try_into()
+        .map_err(|_| NotifyError::TraceContextTooBig)?;
+
+    buf[0] |= TRACE_FLAG;
+    buf.push(trace_len);
+    buf.extend_from_slice(trace);
+    Ok(())
+}
 
-    tx.send(Reply::Response(buf.into()))
+async fn notify(
+    tx: &Sender<Reply>,
+    name: &str,
+    data: &[u8],
+    wide_names: bool,
+) -> Result<(), NotifyError> {
+    tx.send(Reply::Response(encode_notify(name, data, wide_names)?))
         .await
         .map_err(|_| NotifyError::ReceiverClosed)
 }
 
 impl Notifier {
+    /// Returns the id of the connection this notifier belongs to.
+    pub fn id(&self) -> ConnId {
+        self.id
+    }
+
+    /// Returns the total bytes `(read, written)` on the connection, see [`SocketIo::byte_stats`].
+    pub fn byte_stats(&self) -> (u64, u64) {
+        self.byte_stats.get()
+    }
+
+    fn wide_names(&self) -> bool {
+        self.features.contains(Features::WIDE_METHOD_NAMES)
+    }
+
+    /// Returns a [`WeakNotifier`], which doesn't keep the reply channel alive.
+    ///
+    /// Useful for rooms that outlive their members, dead entries can be pruned lazily
+    /// once [`WeakNotifier::upgrade`] returns `None`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// room.insert(notifier.id(), notifier.downgrade());
+    /// room.retain(|_, member| member.upgrade().is_some());
+    /// ```
+    pub fn downgrade(&self) -> WeakNotifier {
+        WeakNotifier {
+            id: self.id,
+            tx: self.tx.downgrade(),
+            features: self.features,
+            byte_stats: self.byte_stats.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
     /// Sends a notification with the given name and data.
     pub async fn notify(&self, name: &str, data: impl AsRef<[u8]>) -> Result<(), NotifyError> {
-        notify(&self.tx, name, data.as_ref()).await
+        notify(&self.tx, name, data.as_ref(), self.wide_names()).await
     }
-}
 
-impl SocketIo {
-    /// Returns a `Notifier` for sending notifications.
-    pub fn notifier(&self) -> Notifier {
-        Notifier {
-            tx: self.tx.clone(),
+    /// Sends many notifications in order, queued as a single reply.
+    ///
+    /// Every notification is encoded before any is sent, so if one is invalid
+    /// (e.g. `NotifyError::EventNameTooBig`) none of them are sent.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// notifier.notify_all([("join", &b"alice"[..]), ("join", b"bob")]).await?;
+    /// ```
+    pub async fn notify_all<'a>(
+        &self,
+        items: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    ) -> Result<(), NotifyError> {
+        let frames = items
+            .into_iter()
+            .map(|(name, data)| encode_notify(name, data, self.wide_names()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if frames.is_empty() {
+            return Ok(());
         }
+        self.tx
+            .send(Reply::Batch(frames))
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)
     }
 
-    /// Sends a notification with the given name and data.
-    pub async fn notify(&mut self, name: &str, data: impl AsRef<[u8]>) -> Result<(), NotifyError> {
-        notify(&self.tx, name, data.as_ref()).await
+    /// Returns the number of replies that can still be queued, before sends have to wait.
+    ///
+    /// Reaching zero means the peer isn't keeping up with the sent data.
+    pub fn capacity(&self) -> usize {
+        self.tx.capacity()
     }
 
-    /// Creates a new `SocketIo` instance with the specified reader, writer, and buffer size.
+    /// Returns the size of the reply channel, see [`SocketIoConfig::channel_buffer`].
+    pub fn max_capacity(&self) -> usize {
+        self.tx.max_capacity()
+    }
+
+    /// Completes once the connection can no longer be written to, i.e. it was closed,
+    /// or a write failed. Further notifications fail with `NotifyError::ReceiverClosed`.
     ///
-    /// # Arguments
+    /// ### Example
     ///
-    /// * `reader` - The source for reading data.
-    /// * `writer` - The destination for writing data.
-    /// * `buffer` - The size of the buffer for the channel.
-    pub fn new<I, O>(reader: I, writer: O, buffer: usize) -> Self
-    where
-        I: Unpin + AsyncRead + Send + 'static,
-        O: Unpin + AsyncWrite + Send + 'static,
-    {
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<Reply>(buffer);
-        let mut ws_writer = WebSocket::server(writer);
-        tokio::spawn(async move {
-            loop {
-                while let Some(reply) = rx.recv().await {
-                    let o = match reply {
-                        Reply::Ping(data) => ws_writer.send_pong(data).await,
-                        Reply::Response(data) => ws_writer.send(&data[..]).await,
-                    };
-                    if o.is_err() {
-                        break;
+    /// ```rust
+    /// notifier.closed().await;
+    /// room.remove(&id);
+    /// ```
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+
+    /// Sends a notification as a WebSocket text frame, instead of a binary one.
+    ///
+    /// Fails with `NotifyError::InvalidText` if the event name is 128 bytes or longer,
+    /// as the encoded frame wouldn't be valid UTF-8.
+    pub async fn notify_text(&self, name: &str, data: &str) -> Result<(), NotifyError> {
+        let buf = encode_notify(name, data.as_bytes(), self.wide_names())?;
+        if std::str::from_utf8(&buf).is_err() {
+            return Err(NotifyError::InvalidText);
+        }
+        self.tx
+            .send(Reply::Text(buf))
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)
+    }
+
+    /// Sends a notification whose payload is read from `reader`, as a fragmented message,
+    /// so that a large payload is never buffered as a whole.
+    ///
+    /// The first fragment carries the notification header (op code and event name),
+    /// followed by up to [`STREAM_FRAGMENT_SIZE`] bytes of payload per fragment.
+    /// Other replies of the connection are queued until the message is complete.
+    ///
+    /// If reading fails midway, this returns `NotifyError::Io` and the connection stops
+    /// writing, as the partially sent message can't be retracted.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let file = tokio::fs::File::open("report.csv").await?;
+    /// notifier.notify_stream("report", file).await?;
+    /// ```
+    pub async fn notify_stream(
+        &self,
+        name: &str,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> Result<(), NotifyError> {
+        let header = encode_notify(name, &[], self.wide_names())?;
+        let (fragments, rx) = tokio::sync::mpsc::channel(1);
+        self.tx
+            .send(Reply::Stream(rx))
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)?;
+
+        let mut fragment = BytesMut::with_capacity(STREAM_FRAGMENT_SIZE);
+        fragment.extend_from_slice(&header);
+        loop {
+            let len = reader
+                .read_buf(&mut fragment)
+                .await
+                .map_err(NotifyError::Io)?;
+            if len == 0 || fragment.len() >= STREAM_FRAGMENT_SIZE {
+                fragments
+                    .send(Some(fragment.split().freeze()))
+                    .await
+                    .map_err(|_| NotifyError::ReceiverClosed)?;
+                fragment.reserve(STREAM_FRAGMENT_SIZE);
+            }
+            if len == 0 {
+                break;
+            }
+        }
+        fragments
+            .send(None)
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)
+    }
+
+    /// Sends a notification without waiting for room in the channel.
+    ///
+    /// Returns `TryNotifyError::Full` if the peer isn't keeping up, so that a slow client
+    /// can be skipped or disconnected, instead of stalling the sender.
+    /// This is also reported to [`Metrics::on_backpressure`].
+    pub fn try_notify(&self, name: &str, data: impl AsRef<[u8]>) -> Result<(), TryNotifyError> {
+        let buf = encode_notify(name, data.as_ref(), self.wide_names())
+            .map_err(|_| TryNotifyError::EventNameTooBig)?;
+
+        self.tx
+            .try_send(Reply::Response(buf))
+            .map_err(|err| match err {
+                TrySendError::Full(_) => {
+                    if let Some(metrics) = &self.metrics {
+                        metrics.on_backpressure(self.id);
+                    }
+                    TryNotifyError::Full
+                }
+                TrySendError::Closed(_) => TryNotifyError::ReceiverClosed,
+            })
+    }
+
+    /// Sends a notification carrying a trace context (e.g. W3C `traceparent` bytes).
+    ///
+    /// Fails with [`NotifyError::Unsupported`] if the peer doesn't support
+    /// [`Features::TRACE_CONTEXT`]. An empty trace context sends a plain notification.
+    pub async fn notify_traced(
+        &self,
+        trace_context: &[u8],
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), NotifyError> {
+        if !trace_context.is_empty() && !self.features.contains(Features::TRACE_CONTEXT) {
+            return Err(NotifyError::Unsupported(Features::TRACE_CONTEXT));
+        }
+        let buf = encode_traced_notify(trace_context, name, data.as_ref(), self.wide_names())?;
+        self.tx
+            .send(Reply::Response(buf))
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)
+    }
+
+    /// Sends a notification that was encoded ahead of time with [`SocketIo::prepare_notify`].
+    ///
+    /// Fails with `NotifyError::EventNameTooBig` if the event name is longer than 255 bytes,
+    /// and the peer didn't negotiate wide method names.
+    pub async fn send_prepared(&self, msg: &PreparedMessage) -> Result<(), NotifyError> {
+        if msg.buf[0] & WIDE_NAME_FLAG != 0 && !self.wide_names() {
+            return Err(NotifyError::EventNameTooBig);
+        }
+        self.tx
+            .send(Reply::Response(msg.buf.clone()))
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)
+    }
+
+    /// Sends a notification and waits until the writer has written it to the underlying stream.
+    ///
+    /// Unlike [`Notifier::notify`], which returns once the message is queued, this resolves
+    /// only after the bytes were handed to the connection, making it suitable for critical
+    /// notifications (e.g. "you've been kicked") sent right before closing.
+    pub async fn notify_flushed(
+        &self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), NotifyError> {
+        let buf = encode_notify(name, data.as_ref(), self.wide_names())?;
+        let (ack, written) = oneshot::channel();
+        self.tx
+            .send(Reply::Flushed(buf, ack))
+            .await
+            .map_err(|_| NotifyError::ReceiverClosed)?;
+
+        written.await.map_err(|_| NotifyError::ReceiverClosed)
+    }
+}
+
+impl<S> SocketIo<S> {
+    /// Returns the negotiated WebSocket subprotocol, e.g. [`PROTOCOL_V2`].
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Returns the optional wire format features that can be used with the peer.
+    ///
+    /// These are the features of the negotiated [`SocketIo::protocol`], plus the ones
+    /// enabled explicitly with [`SocketIoBuilder`].
+    ///
+    /// APIs that need a feature the peer doesn't support fail instead of sending a frame
+    /// it can't parse, e.g. notifying with a method name longer than 255 bytes
+    /// fails with [`NotifyError::EventNameTooBig`] without [`Features::WIDE_METHOD_NAMES`],
+    /// and [`Response::send_error`] fails with [`ResponseError::Unsupported`]
+    /// without [`Features::ERROR_FRAMES`].
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// if socket.features().contains(Features::WIDE_METHOD_NAMES) {
+    ///     socket.notify(&long_name, data).await?;
+    /// }
+    /// ```
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Returns the id of this connection, shared by its [`Notifier`]s.
+    pub fn id(&self) -> ConnId {
+        self.id
+    }
+
+    /// Returns the per-connection state, see [`SocketIo::with_state`].
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns a mutable reference to the per-connection state.
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Attaches per-connection state (e.g. an authenticated session), replacing the current one.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let mut socket = SocketIo::new(reader, writer, 16).with_state(session);
+    /// let user = &socket.state().user;
+    /// ```
+    pub fn with_state<T>(self, state: T) -> SocketIo<T> {
+        let SocketIo {
+            ws,
+            tx,
+            writer,
+            resetter,
+            abandoned,
+            payload_limits,
+            stream_buf,
+            max_message_len,
+            is_streaming,
+            stream_calls,
+            stream_body,
+            read_timeout,
+            max_in_flight_calls,
+            heartbeats,
+            wide_names,
+            binary_names,
+            protocol,
+            features,
+            id,
+            next_call_id,
+            pending_calls,
+            epoch,
+            last_rtt,
+            byte_stats,
+            metrics,
+            #[cfg(feature = "reject-hook")]
+            on_rejected_frame,
+            state: _,
+        } = self;
+        SocketIo {
+            ws,
+            tx,
+            writer,
+            resetter,
+            abandoned,
+            payload_limits,
+            stream_buf,
+            max_message_len,
+            is_streaming,
+            stream_calls,
+            stream_body,
+            read_timeout,
+            max_in_flight_calls,
+            heartbeats,
+            wide_names,
+            binary_names,
+            protocol,
+            features,
+            id,
+            next_call_id,
+            pending_calls,
+            epoch,
+            last_rtt,
+            byte_stats,
+            metrics,
+            #[cfg(feature = "reject-hook")]
+            on_rejected_frame,
+            state,
+        }
+    }
+
+    /// Returns the round-trip time measured by the last keepalive ping.
+    ///
+    /// `None` until a pong is received, requires [`SocketIoBuilder::keepalive`].
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.last_rtt
+    }
+
+    /// Returns the total bytes `(read, written)` on this connection so far.
+    ///
+    /// Counts the payload of data messages, excluding WebSocket framing and control
+    /// frames. Written bytes are counted once they are flushed to the underlying stream.
+    pub fn byte_stats(&self) -> (u64, u64) {
+        self.byte_stats.get()
+    }
+
+    /// Returns a `Notifier` for sending notifications.
+    pub fn notifier(&self) -> Notifier {
+        Notifier {
+            id: self.id,
+            tx: self.tx.clone(),
+            features: self.features,
+            byte_stats: self.byte_stats.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// Calls a rpc method on the client, and resolves with its response.
+    ///
+    /// If the client replies with an error frame, the call fails with an `io::Error`
+    /// wrapping [`RemoteError`].
+    ///
+    /// The returned future doesn't borrow `self`. The response is received by
+    /// [`SocketIo::recv`], so it must keep running while the call is awaited,
+    /// e.g. by spawning the returned future. Dropping the future gives up on the call,
+    /// the client is sent a `Reset` frame if it's still waiting for the response.
+    ///
+    /// Ids of these calls are allocated separately from the ids chosen by the client,
+    /// responses are told apart by their frame type, so the two never collide.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let call = socket.call("confirm", "Are you sure?");
+    /// tokio::spawn(async move {
+    ///     let answer = call.await?;
+    /// });
+    /// ```
+    pub fn call(
+        &mut self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> impl Future<Output = io::Result<Vec<u8>>> + Send + 'static {
+        self.call_traced(&[], name, data)
+    }
+
+    /// Calls a rpc method on the client with a trace context (e.g. W3C `traceparent` bytes),
+    /// see [`SocketIo::call`].
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the peer doesn't support
+    /// [`Features::TRACE_CONTEXT`]. An empty trace context sends a plain request.
+    pub fn call_traced(
+        &mut self,
+        trace_context: &[u8],
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> impl Future<Output = io::Result<Vec<u8>>> + Send + 'static {
+        let supported =
+            if trace_context.is_empty() || self.features.contains(Features::TRACE_CONTEXT) {
+                Ok(())
+            } else {
+                Err(NotifyError::Unsupported(Features::TRACE_CONTEXT))
+            };
+        let wide_names = self.wide_names;
+        let (resolve, response) = oneshot::channel();
+        let chunks = Vec::new();
+        let (mut guard, frame) = self.start_call(PendingCall::Buffered { resolve, chunks }, |id| {
+            supported.and_then(|_| encode_call(id, trace_context, name, data.as_ref(), wide_names))
+        });
+        async move {
+            let frame = frame.map_err(io::Error::from)?;
+            guard.send(frame).await?;
+
+            response
+                .await
+                .unwrap_or_else(|_| Err(io::ErrorKind::ConnectionAborted.into()))
+        }
+    }
+
+    /// Like [`SocketIo::call`], but resolves once the request is sent, with a
+    /// [`ResponseChunks`] stream that yields the chunks of the response as they arrive,
+    /// see [`Features::CHUNKED_RESPONSES`].
+    ///
+    /// Unlike [`SocketIo::call`], the chunks aren't buffered, so the response isn't bound by
+    /// [`SocketIoBuilder::max_payload_size`]. Dropping the stream gives up on the call.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let call = socket.call_chunked("tail", "app.log");
+    /// tokio::spawn(async move {
+    ///     let mut lines = call.await?;
+    ///     while let Some(line) = lines.next().await { ... }
+    /// });
+    /// ```
+    pub fn call_chunked(
+        &mut self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> impl Future<Output = io::Result<ResponseChunks>> + Send + 'static {
+        let wide_names = self.wide_names;
+        let (tx, chunks) = ResponseChunks::channel(16);
+        let (mut guard, frame) = self.start_call(PendingCall::Streamed(tx), |id| {
+            encode_call(id, &[], name, data.as_ref(), wide_names)
+        });
+        async move {
+            let frame = frame.map_err(io::Error::from)?;
+            guard.send(frame).await?;
+            Ok(chunks.guarded(guard))
+        }
+    }
+
+    /// Allocates the id of a new call, and registers it as pending if `encode` succeeds.
+    fn start_call(
+        &mut self,
+        call: PendingCall,
+        encode: impl FnOnce(u32) -> Result<Bytes, NotifyError>,
+    ) -> (CallGuard, Result<Bytes, NotifyError>) {
+        let mut calls = lock(&self.pending_calls);
+        // Skip ids that are still pending, once the id space wrapped around.
+        let id = loop {
+            let id = self.next_call_id;
+            self.next_call_id = self.next_call_id.wrapping_add(1);
+            if !calls.contains_key(&id) {
+                break id;
+            }
+        };
+        let frame = encode(id);
+        if frame.is_ok() {
+            calls.insert(id, call);
+        }
+        drop(calls);
+
+        let guard = CallGuard {
+            id,
+            calls: self.pending_calls.clone(),
+            tx: self.tx.clone(),
+            sent: false,
+        };
+        (guard, frame)
+    }
+
+    /// Sends a notification with the given name and data.
+    pub async fn notify(&mut self, name: &str, data: impl AsRef<[u8]>) -> Result<(), NotifyError> {
+        notify(&self.tx, name, data.as_ref(), self.wide_names).await
+    }
+}
+
+impl SocketIo {
+    /// Encodes a notification once, so that it can be broadcast to many clients.
+    ///
+    /// Event names longer than 255 bytes are encoded in the wide form, which can only be
+    /// sent to peers that negotiated [`PROTOCOL_V2`] or later.
+    pub fn prepare_notify(
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> Result<PreparedMessage, NotifyError> {
+        Ok(PreparedMessage {
+            buf: encode_notify(name, data.as_ref(), true)?,
+        })
+    }
+
+    /// Creates a new `SocketIo` instance with the specified reader, writer, and buffer size.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source for reading data.
+    /// * `writer` - The destination for writing data.
+    /// * `buffer` - The size of the buffer for the channel.
+    ///
+    /// Use [`SocketIoBuilder`] or [`SocketIoConfig`] for more options.
+    pub fn new<I, O>(reader: I, writer: O, buffer: usize) -> Self
+    where
+        I: Unpin + AsyncRead + Send + 'static,
+        O: Unpin + AsyncWrite + Send + 'static,
+    {
+        Self::from_config(reader, writer, SocketIoConfig::new(buffer))
+    }
+
+    /// Creates a new `SocketIo` instance from a full-duplex stream, e.g. a `TcpStream`.
+    ///
+    /// The stream is split into a reader and a writer with [`tokio::io::split`].
+    pub fn from_stream<S>(stream: S, buffer: usize) -> Self
+    where
+        S: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        Self::new(reader, writer, buffer)
+    }
+
+    /// Creates a new `SocketIo` instance with the specified reader, writer, and config.
+    pub fn from_config<I, O>(reader: I, writer: O, config: SocketIoConfig) -> Self
+    where
+        I: Unpin + AsyncRead + Send + 'static,
+        O: Unpin + AsyncWrite + Send + 'static,
+    {
+        Self::with_role(reader, writer, config, false)
+    }
+
+    /// Creates a new `SocketIo` instance that receives frames from `source`, instead of
+    /// decoding them from a reader, e.g. to use another WebSocket implementation.
+    ///
+    /// Replies are written to `writer`. [`SocketIoConfig::max_frame_size`] isn't applied,
+    /// the source has to bound the size of a frame itself.
+    pub fn from_source<O>(source: impl FrameSource, writer: O, config: SocketIoConfig) -> Self
+    where
+        O: Unpin + AsyncWrite + Send + 'static,
+    {
+        Self::with_source(Box::new(source), writer, config, false)
+    }
+
+    /// Creates the instance for the server end, or the client end (masking outgoing frames)
+    /// of the connection.
+    fn with_role<I, O>(reader: I, writer: O, config: SocketIoConfig, client: bool) -> Self
+    where
+        I: Unpin + AsyncRead + Send + 'static,
+        O: Unpin + AsyncWrite + Send + 'static,
+    {
+        let mut ws = match client {
+            true => WebSocket::client(reader),
+            false => WebSocket::server(reader),
+        };
+        if let Some(max) = config.max_frame_size.or(config.max_payload_size) {
+            ws.max_payload_len = max;
+        }
+        Self::with_source(Box::new(ws), writer, config, client)
+    }
+
+    fn with_source<O>(
+        ws: Box<dyn FrameSource>,
+        writer: O,
+        config: SocketIoConfig,
+        client: bool,
+    ) -> Self
+    where
+        O: Unpin + AsyncWrite + Send + 'static,
+    {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Reply>(config.channel_buffer);
+        let writer = BufWriter::new(writer);
+        let mut ws_writer = match client {
+            true => WebSocket::client(writer),
+            false => WebSocket::server(writer),
+        };
+        let write_timeout = config.write_timeout;
+        let byte_stats = Arc::new(ByteStats::default());
+        let stats = byte_stats.clone();
+        let writer = tokio::spawn(async move {
+            let mut replies = Vec::with_capacity(WRITE_BATCH);
+            while rx.recv_many(&mut replies, WRITE_BATCH).await != 0 {
+                let mut acks = Vec::new();
+                let mut close = None;
+                let mut written = 0;
+                let write = async {
+                    for reply in replies.drain(..) {
+                        match reply {
+                            Reply::Ping(data) => {
+                                timed(write_timeout, ws_writer.send_pong(data)).await?
+                            }
+                            Reply::KeepAlive(sent) => {
+                                let micros = sent.as_micros() as u64;
+                                timed(write_timeout, ws_writer.send_ping(micros.to_be_bytes()))
+                                    .await?
+                            }
+                            Reply::Response(data) => {
+                                timed(write_timeout, ws_writer.send(&data[..])).await?;
+                                written += data.len();
+                            }
+                            Reply::Batch(frames) => {
+                                for frame in frames {
+                                    timed(write_timeout, ws_writer.send(&frame[..])).await?;
+                                    written += frame.len();
+                                }
+                            }
+                            Reply::Stream(mut fragments) => {
+                                let mut opcode = 2; // binary
+                                loop {
+                                    // The sender failed midway, the message can't be completed.
+                                    let fragment = fragments
+                                        .recv()
+                                        .await
+                                        .ok_or(io::ErrorKind::UnexpectedEof)?;
+
+                                    let fin = fragment.is_none();
+                                    let data = fragment.unwrap_or_default();
+                                    let frame = WsFrame {
+                                        fin,
+                                        opcode,
+                                        data: &data,
+                                    };
+                                    timed(write_timeout, ws_writer.send(frame)).await?;
+                                    written += data.len();
+                                    if fin {
+                                        break;
+                                    }
+                                    opcode = 0; // continuation
+                                }
+                            }
+                            Reply::Text(data) => {
+                                // SAFETY: `Reply::Text` is only constructed from valid UTF-8.
+                                let text = unsafe { std::str::from_utf8_unchecked(&data) };
+                                timed(write_timeout, ws_writer.send(text)).await?;
+                                written += data.len();
+                            }
+                            Reply::Flushed(data, ack) => {
+                                timed(write_timeout, ws_writer.send(&data[..])).await?;
+                                written += data.len();
+                                acks.push(ack);
+                            }
+                            Reply::Close(code, reason, ack) => {
+                                close = Some((code, reason, ack));
+                                break;
+                            }
+                        }
+                    }
+                    timed(write_timeout, ws_writer.stream.flush()).await
+                };
+                if let Err(err) = write.await {
+                    if err.kind() == io::ErrorKind::TimedOut {
+                        // The peer isn't draining its socket, tell it why it's dropped.
+                        let ws = match client {
+                            true => WebSocket::client(&mut ws_writer.stream),
+                            false => WebSocket::server(&mut ws_writer.stream),
+                        };
+                        let _ = timed(write_timeout, ws.close((1008, "write timed out"))).await;
+                    }
+                    break;
+                }
+                stats.written.fetch_add(written as u64, Ordering::Relaxed);
+                for ack in acks {
+                    let _ = ack.send(());
+                }
+                if let Some((code, reason, ack)) = close {
+                    // `close` consumes the `WebSocket`, so the close frame is written
+                    // unbuffered, after the replies queued before it were flushed.
+                    let stream = ws_writer.stream.get_mut();
+                    let ws = match client {
+                        true => WebSocket::client(stream),
+                        false => WebSocket::server(stream),
+                    };
+                    let _ = ack.send(ws.close((code, &*reason)).await);
+                    return;
+                }
+            }
+        });
+        let epoch = Instant::now();
+        if let Some(interval) = config.keepalive {
+            let tx = tx.downgrade();
+            tokio::spawn(async move {
+                let start = tokio::time::Instant::now() + interval;
+                let mut ticker = tokio::time::interval_at(start, interval);
+                loop {
+                    ticker.tick().await;
+                    let Some(tx) = tx.upgrade() else { break };
+                    if tx.send(Reply::KeepAlive(epoch.elapsed())).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        let mut features = Features::from_protocol(&config.protocol);
+        if config.wide_method_names {
+            features = features | Features::WIDE_METHOD_NAMES;
+        }
+        if config.deadlines {
+            features = features | Features::DEADLINES;
+        }
+        if config.trace_context {
+            features = features | Features::TRACE_CONTEXT;
+        }
+        Self {
+            ws: FrameReader::new(ws),
+            tx,
+            writer,
+            resetter: Default::default(),
+            abandoned: Vec::new(),
+            payload_limits: HashMap::new(),
+            stream_buf: Vec::new(),
+            max_message_len: config.max_payload_size,
+            is_streaming: false,
+            stream_calls: false,
+            stream_body: None,
+            read_timeout: config.read_timeout,
+            max_in_flight_calls: config.max_in_flight_calls,
+            heartbeats: config.heartbeats,
+            wide_names: features.contains(Features::WIDE_METHOD_NAMES),
+            binary_names: config.binary_method_names,
+            protocol: config.protocol,
+            features,
+            id: ConnId::next(),
+            next_call_id: 1,
+            pending_calls: Default::default(),
+            epoch,
+            last_rtt: None,
+            byte_stats,
+            metrics: config.metrics,
+            #[cfg(feature = "reject-hook")]
+            on_rejected_frame: None,
+            state: (),
+        }
+    }
+}
+
+impl<S> SocketIo<S> {
+    /// Closes the connection with the given close code and reason.
+    ///
+    /// Replies queued before this call (responses, notifications) are written first,
+    /// then the close frame is sent and the writer task is joined. Any `Notifier` or
+    /// `Response` used afterward fails with `ReceiverClosed`.
+    ///
+    /// Simply dropping the `SocketIo` (e.g. when [`SocketIo::recv`] fails) doesn't send
+    /// a close frame, the peer only sees the connection drop (code `1006` in browsers).
+    /// Call `close` after the receive loop to end the connection cleanly, it fails if
+    /// the connection is already broken.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// while let Ok(procedure) = socket.recv().await { ... }
+    /// let _ = socket.close(1000, "bye").await;
+    /// ```
+    pub async fn close(self, code: u16, reason: &str) -> io::Result<()> {
+        let (ack, closed) = oneshot::channel();
+        self.tx
+            .send(Reply::Close(code, reason.into(), ack))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+        let result = closed
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::BrokenPipe.into()));
+
+        let _ = self.writer.await;
+        result
+    }
+
+    /// Gracefully closes the connection, e.g. for a rolling deploy.
+    ///
+    /// Stops receiving procedures, waits until every in-flight rpc call is answered
+    /// (or its [`Response`] is dropped), then closes the connection with code `1001`
+    /// (going away) after the queued replies are written, see [`SocketIo::close`].
+    ///
+    /// The peer can still reset calls, but those frames aren't read anymore, so bound
+    /// the wait with a timeout if handlers may never finish.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// loop {
+    ///     tokio::select! {
+    ///         _ = shutdown_signal.recv() => break,
+    ///         procedure = socket.recv() => { ... }
+    ///     }
+    /// }
+    /// socket.shutdown().await?;
+    /// ```
+    pub async fn shutdown(self) -> io::Result<()> {
+        loop {
+            let idle = self.resetter.idle.notified();
+            if lock(&self.resetter.calls).is_empty() {
+                break;
+            }
+            idle.await;
+        }
+        self.close(1001, "going away").await
+    }
+
+    /// Returns the handle of the writer task, which writes replies to the underlying stream.
+    ///
+    /// The task finishes once a write fails, or every `Sender` of the reply channel
+    /// (`SocketIo`, `Notifier`, `Response`, ...) is dropped.
+    pub fn writer_handle(&self) -> &JoinHandle<()> {
+        &self.writer
+    }
+
+    /// Receives the next `Procedure` (either a rpc or notification).
+    ///
+    /// ## Connection State
+    /// - Returns `io::ErrorKind::ConnectionReset` when an error event occurs.
+    /// - Returns `io::ErrorKind::ConnectionAborted` when a close event is received.
+    ///
+    /// On a fatal error (see [`is_fatal`](error::is_fatal)), every in-flight rpc is reset,
+    /// see [`SocketIo::abandoned_calls`]. A rejected frame (`InvalidData`) leaves them intact.
+    ///
+    /// ## Cancel Safety
+    ///
+    /// This method is cancellation safe, so it can be used as a `tokio::select!` branch.
+    /// A frame that is being read when the future is dropped, and the fragments of a
+    /// message received so far, are kept by the `SocketIo`, and the next call resumes
+    /// with them.
+    ///
+    /// Once a frame is read, it's handled before the next one. Handling only waits for
+    /// room in the reply queue (e.g. to answer a ping), or in the body of a streamed call,
+    /// a reply (or chunk) waiting there is dropped along with the future.
+    pub async fn recv(&mut self) -> io::Result<Procedure> {
+        self.recv_procedure(false).await
+    }
+
+    /// Like [`SocketIo::recv`], but reassembles fragmented messages in `scratch`, so that its
+    /// capacity is reused across calls instead of growing a new buffer for each message.
+    ///
+    /// `scratch` is cleared first, and left empty (with its capacity) once this returns.
+    /// A reassembled message is copied into its [`Request`], which needs a single
+    /// allocation of the exact size.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let mut scratch = Vec::with_capacity(64 * 1024);
+    /// while let Ok(procedure) = socket.recv_into(&mut scratch).await { ... }
+    /// ```
+    pub async fn recv_into(&mut self, scratch: &mut Vec<u8>) -> io::Result<Procedure> {
+        // `scratch` takes over the fragments received so far, e.g. by a dropped `recv`.
+        scratch.clear();
+        scratch.append(&mut self.stream_buf);
+        std::mem::swap(scratch, &mut self.stream_buf);
+        let result = self.recv_procedure(true).await;
+        std::mem::swap(scratch, &mut self.stream_buf);
+        self.stream_buf.append(scratch);
+        result
+    }
+
+    /// Receives the next `Procedure`, `keep_buf` copies a reassembled message out of
+    /// `stream_buf` instead of moving the buffer into the request.
+    async fn recv_procedure(&mut self, keep_buf: bool) -> io::Result<Procedure> {
+        let result = async {
+            loop {
+                match self.read_event().await? {
+                    Event::Data { ty, data } => match ty {
+                        DataType::Complete(_) => {
+                            if let ControlFlow::Break(p) = self
+                                .into_event(data, false)
+                                .await
+                                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+                            {
+                                return Ok(p);
+                            }
+                        }
+                        DataType::Stream(stream) => {
+                            if let Stream::Start(_) = stream {
+                                self.stream_buf.clear();
+                                self.stream_body = None;
+                                self.is_streaming = true;
+                                if self.stream_calls {
+                                    if let Ok(Frame::Call(id, header)) =
+                                        parse_frame(&data, self.features, self.binary_names)
+                                    {
+                                        // Duplicates and calls over the limit are buffered,
+                                        // and rejected by `into_event`.
+                                        if !self.is_in_flight(id) && !self.is_at_call_limit() {
+                                            return Ok(self.stream_call(id, header, &data));
+                                        }
+                                    }
+                                }
+                            }
+                            if !self.is_streaming {
+                                continue;
+                            }
+                            if let Some(body) = &self.stream_body {
+                                let _ = body.send(Some(data)).await;
+                                if let Stream::End(_) = stream {
+                                    let _ = body.send(None).await;
+                                    self.stream_body = None;
+                                    self.is_streaming = false;
+                                }
+                                continue;
+                            }
+                            if self
+                                .max_message_len
+                                .is_some_and(|max| self.stream_buf.len() + data.len() > max)
+                            {
+                                self.reject_oversized_message().await;
+                                continue;
+                            }
+                            self.stream_buf.extend_from_slice(&data);
+                            if let Stream::End(_) = stream {
+                                self.is_streaming = false;
+                                let buf: Box<[u8]> = match keep_buf {
+                                    true => self.stream_buf.drain(..).as_slice().into(),
+                                    false => std::mem::take(&mut self.stream_buf).into(),
+                                };
+                                if let ControlFlow::Break(p) =
+                                    self.into_event(buf, true).await.map_err(|err| {
+                                        io::Error::new(io::ErrorKind::InvalidData, err)
+                                    })?
+                                {
+                                    return Ok(p);
+                                }
+                            }
+                        }
+                    },
+                    Event::Ping(data) => {
+                        let _ = self.tx.send(Reply::Ping(data.into())).await;
+                    }
+                    Event::Pong(data) => self.on_pong(&data),
+                    Event::Error(err) => {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionReset, err))
+                    }
+                    Event::Close { code, reason } => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionAborted,
+                            ConnClose { code, reason },
+                        ))
+                    }
+                }
+            }
+        }
+        .await;
+        if result.as_ref().is_err_and(error::is_fatal) {
+            self.stream_body = None;
+            lock(&self.pending_calls).clear();
+            self.abandoned.clear();
+            for (id, reset_inner) in lock(&self.resetter.calls).drain() {
+                reset_inner.reset();
+                self.abandoned.push(id);
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            match &result {
+                Ok(Procedure::Notify(req)) => metrics.on_notify(req.method()),
+                Ok(Procedure::Call(req, ..) | Procedure::StreamCall(req, ..)) => {
+                    metrics.on_call(req.method())
+                }
+                Ok(Procedure::Heartbeat(_)) => {}
+                Err(err) => metrics.on_error(err),
+            }
+        }
+        #[cfg(feature = "tracing")]
+        if let Ok(procedure) = &result {
+            let len = match procedure {
+                Procedure::Call(req, ..)
+                | Procedure::Notify(req)
+                | Procedure::StreamCall(req, ..) => req.data().len(),
+                Procedure::Heartbeat(data) => data.len(),
+            };
+            tracing::debug!(
+                method = procedure.method(),
+                id = procedure.id(),
+                len,
+                "received procedure"
+            );
+        }
+        result
+    }
+
+    /// Receives procedures and calls `handler` with each of them, until the connection fails.
+    ///
+    /// Malformed frames are skipped, as with [`Router::serve`].
+    /// The handler is awaited before the next procedure is received, spawn long running work
+    /// (e.g. with [`AbortController::spawn_and_abort_on_reset`]) to keep receiving.
+    /// Returns the error that ended the connection.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let err = socket
+    ///     .serve(|procedure| async {
+    ///         if let Procedure::Call(req, res, _) = procedure {
+    ///             let _ = res.send(req.data()).await;
+    ///         }
+    ///     })
+    ///     .await;
+    /// ```
+    pub async fn serve<F, Fut>(mut self, mut handler: F) -> io::Error
+    where
+        F: FnMut(Procedure) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            match self.recv().await {
+                Ok(procedure) => handler(procedure).await,
+                Err(err) if error::is_fatal(&err) => return err,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Like [`SocketIo::serve`], but the future returned by `handler` for a rpc call
+    /// is spawned on its own task, so a slow call doesn't hold up the ones after it.
+    ///
+    /// Notifications and heartbeats are still awaited in the order they arrive,
+    /// whereas calls may complete (and be replied to) in any order, and run concurrently
+    /// with the notifications received after them.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let err = socket
+    ///     .serve_concurrent(|procedure| async move {
+    ///         if let Procedure::Call(req, res, _) = procedure {
+    ///             let _ = res.send(slow_lookup(req.data()).await).await;
+    ///         }
+    ///     })
+    ///     .await;
+    /// ```
+    pub async fn serve_concurrent<F, Fut>(mut self, mut handler: F) -> io::Error
+    where
+        F: FnMut(Procedure) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        loop {
+            match self.recv().await {
+                Ok(procedure @ (Procedure::Call(..) | Procedure::StreamCall(..))) => {
+                    tokio::spawn(handler(procedure));
+                }
+                Ok(procedure) => handler(procedure).await,
+                Err(err) if error::is_fatal(&err) => return err,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Like [`SocketIo::recv`], but returns the close code and reason of the peer directly,
+    /// instead of wrapped in an `io::Error`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// loop {
+    ///     match socket.recv_event().await {
+    ///         RecvOutcome::Procedure(procedure) => { ... }
+    ///         RecvOutcome::Closed(ConnClose { code, reason }) => break,
+    ///         RecvOutcome::Errored(err) => { ... }
+    ///     }
+    /// }
+    /// ```
+    pub async fn recv_event(&mut self) -> RecvOutcome {
+        match self.recv().await {
+            Ok(procedure) => RecvOutcome::Procedure(procedure),
+            Err(err) if err.get_ref().is_some_and(|inner| inner.is::<ConnClose>()) => {
+                let close = err
+                    .into_inner()
+                    .and_then(|inner| inner.downcast::<ConnClose>().ok())
+                    .expect("checked above");
+                RecvOutcome::Closed(*close)
+            }
+            Err(err) => RecvOutcome::Errored(err),
+        }
+    }
+
+    /// Resets every in-flight rpc, so that tasks waiting on their `AbortController` stop.
+    ///
+    /// This is what [`SocketIo::recv`] does when the connection fails, but can be triggered
+    /// on demand, e.g. on graceful shutdown or a forced logout. Calls that weren't answered
+    /// yet fail on the caller's side with an `"aborted"` error reply (or, without
+    /// [`Features::ERROR_FRAMES`], the connection is closed with code `1011`), their
+    /// `Response` fails with [`ResponseError::Reset`].
+    pub fn abort_all(&self) {
+        let mut calls = lock(&self.resetter.calls);
+        for (id, reset_inner) in calls.drain() {
+            if reset_inner.abort() {
+                send_detached(&self.tx, call_failure(self.features, id, 1011, "aborted"));
+            }
+        }
+        self.resetter.idle.notify_waiters();
+    }
+
+    /// Discards any partially received (fragmented) message.
+    ///
+    /// After this call, no inbound data is retained: the reassembly buffer is dropped
+    /// (including its allocation), so the next [`SocketIo::recv`] only yields procedures
+    /// from messages that start after this point. Fragments of a message that was
+    /// already in progress are dropped until the next message starts.
+    pub fn reset_read_state(&mut self) {
+        self.stream_buf = Vec::new();
+        self.stream_body = None;
+        self.is_streaming = false;
+    }
+
+    /// When enabled, a rpc sent as a fragmented message is yielded as a [`Procedure::StreamCall`]
+    /// as soon as its first fragment arrives, instead of being buffered entirely in memory.
+    ///
+    /// The first fragment must contain the complete method name, otherwise the call is buffered
+    /// as usual. Payload limits set by [`SocketIo::set_max_payload`] don't apply to streamed calls.
+    pub fn stream_calls(&mut self, enable: bool) {
+        self.stream_calls = enable;
+    }
+
+    fn stream_call(&mut self, id: u32, header: Header, buf: &[u8]) -> Procedure {
+        let data_offset = header.data_offset as usize;
+        let (tx, body) = Body::channel(16);
+        let _ = tx.try_send(Some(buf[data_offset..].into()));
+        self.stream_body = Some(tx);
+
+        let (res, reset) = self.register_call(id, header.deadline);
+        Procedure::StreamCall(
+            Request::new(buf[..data_offset].into(), header, true),
+            body,
+            res,
+            reset,
+        )
+    }
+
+    fn is_in_flight(&self, id: u32) -> bool {
+        lock(&self.resetter.calls).contains_key(&id)
+    }
+
+    fn is_at_call_limit(&self) -> bool {
+        self.max_in_flight_calls
+            .is_some_and(|max| lock(&self.resetter.calls).len() >= max)
+    }
+
+    fn register_call(&self, id: u32, deadline: Option<Duration>) -> (Response, AbortController) {
+        let reset = AbortController::new();
+        lock(&self.resetter.calls).insert(id, reset.inner.clone());
+        if let Some(deadline) = deadline {
+            let inner = Arc::downgrade(&reset.inner);
+            let resetter = self.resetter.clone();
+            let tx = self.tx.downgrade();
+            let features = self.features;
+            let task = tokio::spawn(async move {
+                tokio::time::sleep(deadline).await;
+                // The call is gone, otherwise this task would have been aborted.
+                let Some(inner) = inner.upgrade() else { return };
+                resetter.remove(id, &inner);
+                if inner.expire() {
+                    if let Some(tx) = tx.upgrade() {
+                        let failure = call_failure(features, id, 1011, "deadline exceeded");
+                        let _ = tx.send(failure).await;
+                    }
+                }
+            });
+            *lock(&reset.inner.deadline) = Some(task.abort_handle());
+        }
+        let res = Response {
+            id,
+            tx: self.tx.clone(),
+            resetter: self.resetter.clone(),
+            reset: reset.inner.clone(),
+            features: self.features,
+        };
+        (res, reset)
+    }
+
+    fn on_pong(&mut self, data: &[u8]) {
+        // Unsolicited pongs, or replies to other pings, carry arbitrary payloads.
+        let Ok(micros) = <[u8; 8]>::try_from(data) else {
+            return;
+        };
+        let sent = Duration::from_micros(u64::from_be_bytes(micros));
+        if let Some(rtt) = self.epoch.elapsed().checked_sub(sent) {
+            self.last_rtt = Some(rtt);
+        }
+    }
+
+    async fn read_event(&mut self) -> io::Result<Event> {
+        let event = match self.ws.recv(self.read_timeout).await {
+            Some(event) => event,
+            None => {
+                // The peer is gone (or stuck), tell it why it's dropped.
+                let (ack, _) = oneshot::channel();
+                let reason = "read timed out".into();
+                let _ = self.tx.send(Reply::Close(1008, reason, ack)).await;
+                Err(io::ErrorKind::TimedOut.into())
+            }
+        }?;
+        if let Event::Data { data, .. } = &event {
+            let read = data.len() as u64;
+            self.byte_stats.read.fetch_add(read, Ordering::Relaxed);
+        }
+        Ok(event)
+    }
+
+    /// Returns the ids of the rpc calls that were still in-flight, and therefore reset,
+    /// when [`SocketIo::recv`] last returned a fatal error.
+    pub fn abandoned_calls(&self) -> &[u32] {
+        &self.abandoned
+    }
+
+    /// Limits the payload size of the given method (rpc or notification) to `max` bytes.
+    ///
+    /// Oversized calls are answered with an error frame (see [`Features::ERROR_FRAMES`]),
+    /// and oversized notifications are dropped, without being yielded by [`SocketIo::recv`].
+    pub fn set_max_payload(&mut self, method: &str, max: usize) {
+        self.payload_limits.insert(method.into(), max);
+    }
+
+    fn exceeds_payload_limit(&self, buf: &[u8], header: &Header) -> bool {
+        if self.payload_limits.is_empty() {
+            return false;
+        }
+        let offset = header.method_offset as usize;
+        let method = &buf[offset..offset + header.method_len as usize];
+        let method = std::str::from_utf8(method).unwrap_or_default();
+        match self.payload_limits.get(method) {
+            Some(&max) => buf.len() - header.data_offset as usize > max,
+            None => false,
+        }
+    }
+
+    async fn into_event(
+        &mut self,
+        buf: Box<[u8]>,
+        streamed: bool,
+    ) -> Result<ControlFlow<Procedure>, DynErr> {
+        let frame = match parse_frame(&buf, self.features, self.binary_names) {
+            Ok(frame) => frame,
+            Err(err) => {
+                self.reject_frame(RejectReason::Invalid, &buf);
+                return Err(err.into());
+            }
+        };
+        match frame {
+            Frame::Notify(header) => {
+                if self.exceeds_payload_limit(&buf, &header) {
+                    self.reject_frame(RejectReason::PayloadTooLarge, &buf);
+                    return Ok(ControlFlow::Continue(()));
+                }
+                Ok(ControlFlow::Break(Procedure::Notify(Request::new(
+                    buf, header, streamed,
+                ))))
+            }
+            Frame::Call(id, header) => {
+                if self.exceeds_payload_limit(&buf, &header) {
+                    self.reject_frame(RejectReason::PayloadTooLarge, &buf);
+                    let failure = call_failure(self.features, id, 1009, "payload too large");
+                    let _ = self.tx.send(failure).await;
+                    return Ok(ControlFlow::Continue(()));
+                }
+                if self.is_in_flight(id) {
+                    self.reject_frame(RejectReason::DuplicateCallId(id), &buf);
+                    let failure = call_failure(self.features, id, 1008, "duplicate call id");
+                    let _ = self.tx.send(failure).await;
+                    return Ok(ControlFlow::Continue(()));
+                }
+                if self.is_at_call_limit() {
+                    self.reject_frame(RejectReason::TooManyCalls, &buf);
+                    let failure = call_failure(self.features, id, 1008, "too many requests");
+                    let _ = self.tx.send(failure).await;
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let (res, reset) = self.register_call(id, header.deadline);
+                Ok(ControlFlow::Break(Procedure::Call(
+                    Request::new(buf, header, streamed),
+                    res,
+                    reset,
+                )))
+            }
+            Frame::Reset(id) => {
+                let reset_inner = lock(&self.resetter.calls).remove(&id);
+                match reset_inner {
+                    Some(reset_inner) => {
+                        reset_inner.reset();
+                        if let Some(metrics) = &self.metrics {
+                            metrics.on_reset(id);
+                        }
                     }
+                    None => self.reject_frame(RejectReason::UnknownCallId(id), &buf),
                 }
+                Ok(ControlFlow::Continue(()))
+            }
+            Frame::Response(id) | Frame::Error(id) => {
+                self.resolve_call(id, &buf).await;
+                Ok(ControlFlow::Continue(()))
+            }
+            Frame::Chunk(id) => {
+                self.on_chunk(id, &buf).await;
+                Ok(ControlFlow::Continue(()))
+            }
+            Frame::Heartbeat => {
+                if !self.heartbeats {
+                    self.reject_frame(RejectReason::Invalid, &buf);
+                    return Err(FrameError::UnknownType(buf[0]).into());
+                }
+                let data = Bytes::from(buf).slice(1..);
+                Ok(ControlFlow::Break(Procedure::Heartbeat(data)))
             }
-        });
-        Self {
-            ws: WebSocket::server(Box::new(reader)),
-            tx,
-            resetter: Default::default(),
         }
     }
 
-    /// Receives the next `Procedure` (either a rpc or notification).
-    ///
-    /// ## Connection State
-    /// - Returns `io::ErrorKind::ConnectionReset` when an error event occurs.
-    /// - Returns `io::ErrorKind::ConnectionAborted` when a close event is received.
-    pub async fn recv(&mut self) -> io::Result<Procedure> {
-        let mut buf = Vec::with_capacity(4096);
-        let result = async {
-            loop {
-                match self.ws.recv().await? {
-                    Event::Data { ty, data } => match ty {
-                        DataType::Complete(_) => {
-                            if let ControlFlow::Break(p) = self
-                                .into_event(data)
-                                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
-                            {
-                                return Ok(p);
-                            }
-                        }
-                        DataType::Stream(stream) => {
-                            buf.extend_from_slice(&data);
-                            if let Stream::End(_) = stream {
-                                if let ControlFlow::Break(p) =
-                                    self.into_event(data).map_err(|err| {
-                                        io::Error::new(io::ErrorKind::InvalidData, err)
-                                    })?
-                                {
-                                    return Ok(p);
-                                }
-                            }
-                        }
-                    },
-                    Event::Ping(data) => {
-                        let _ = self.tx.send(Reply::Ping(data)).await;
+    async fn resolve_call(&mut self, id: u32, frame: &[u8]) {
+        let Some(call) = lock(&self.pending_calls).remove(&id) else {
+            return self.reject_frame(RejectReason::UnknownCallId(id), frame);
+        };
+        let data = &frame[5..];
+        match call {
+            PendingCall::Buffered {
+                resolve,
+                mut chunks,
+            } => {
+                let result = match frame[0] {
+                    4 => {
+                        chunks.extend_from_slice(data);
+                        Ok(chunks)
                     }
-                    Event::Pong(_) => {}
-                    Event::Error(err) => {
-                        return Err(io::Error::new(io::ErrorKind::ConnectionReset, err))
+                    _ => Err(io::Error::other(RemoteError(data.into()))),
+                };
+                let _ = resolve.send(result);
+            }
+            PendingCall::Streamed(tx) => {
+                let end = match frame[0] {
+                    4 if data.is_empty() => None,
+                    4 => {
+                        let _ = tx.send(Some(Ok(data.into()))).await;
+                        None
                     }
-                    Event::Close { code, reason } => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::ConnectionAborted,
-                            ConnClose { code, reason },
-                        ))
+                    _ => Some(Err(io::Error::other(RemoteError(data.into())))),
+                };
+                let _ = tx.send(end).await;
+            }
+        }
+    }
+
+    /// Appends a chunk to its pending call. A buffered call whose chunks grow past
+    /// `max_payload_size` fails, and the peer is told to stop with a `Reset` frame.
+    async fn on_chunk(&mut self, id: u32, frame: &[u8]) {
+        let chunk = &frame[5..];
+        let max = self.max_message_len;
+        let call = {
+            let mut calls = lock(&self.pending_calls);
+            match calls.get_mut(&id) {
+                Some(PendingCall::Buffered { chunks, .. }) => {
+                    if max.is_none_or(|max| chunks.len() + chunk.len() <= max) {
+                        chunks.extend_from_slice(chunk);
+                        return;
                     }
+                    calls.remove(&id)
                 }
+                Some(PendingCall::Streamed(tx)) => Some(PendingCall::Streamed(tx.clone())),
+                None => None,
             }
-        }
-        .await;
-        if result.is_err() {
-            for (_, reset_inner) in self.resetter.lock().unwrap().drain() {
-                reset_inner.lock().unwrap().reset();
+        };
+        match call {
+            Some(PendingCall::Streamed(tx)) => {
+                let _ = tx.send(Some(Ok(chunk.into()))).await;
+            }
+            Some(PendingCall::Buffered { resolve, .. }) => {
+                self.reject_frame(RejectReason::PayloadTooLarge, frame);
+                let err = io::Error::new(io::ErrorKind::InvalidData, RejectReason::PayloadTooLarge);
+                let _ = resolve.send(Err(err));
+                let _ = self
+                    .tx
+                    .send(Reply::Response(encode_response(3, id, &[])))
+                    .await;
             }
+            None => self.reject_frame(RejectReason::UnknownCallId(id), frame),
         }
-        result
     }
 
-    fn into_event(&mut self, buf: Box<[u8]>) -> Result<ControlFlow<Procedure>, DynErr> {
-        let reader = &mut &buf[..];
-        let frame_type = get_slice(reader, 1)?[0];
-
-        match frame_type {
-            1 => {
-                let method_len = validate_and_parse_utf8_rpc_name(reader)?;
-                let data_offset = (buf.len() - reader.len()) as u16;
-                Ok(ControlFlow::Break(Procedure::Notify(Request {
-                    buf,
-                    method_offset: 2,
-                    method_len,
-                    data_offset,
-                })))
-            }
-            2 => {
-                let id = parse_rpc_id(reader)?;
-                let method_len = validate_and_parse_utf8_rpc_name(reader)?;
-                let data_offset = (buf.len() - reader.len()) as u16;
+    /// Drops a fragmented message that grew past `max_payload_size`, the rest of its
+    /// fragments are skipped. If it's a rpc call, the caller gets an error reply.
+    async fn reject_oversized_message(&mut self) {
+        self.is_streaming = false;
+        let buf = std::mem::take(&mut self.stream_buf);
+        self.reject_frame(RejectReason::PayloadTooLarge, &buf);
 
-                let reset = AbortController::new();
-                self.resetter
-                    .lock()
-                    .unwrap()
-                    .insert(id, reset.inner.clone());
+        if let Ok(Frame::Call(id, _)) = parse_frame(&buf, self.features, self.binary_names) {
+            let failure = call_failure(self.features, id, 1009, "payload too large");
+            let _ = self.tx.send(failure).await;
+        }
+    }
 
-                Ok(ControlFlow::Break(Procedure::Call(
-                    Request {
-                        buf,
-                        method_offset: 6,
-                        method_len,
-                        data_offset,
-                    },
-                    Response {
-                        id,
-                        tx: self.tx.clone(),
-                        resetter: self.resetter.clone(),
-                    },
-                    reset,
-                )))
-            }
-            3 => {
-                let id = parse_rpc_id(reader)?;
-                if let Some(reset_inner) = self.resetter.lock().unwrap().remove(&id) {
-                    reset_inner.lock().unwrap().reset();
-                }
-                Ok(ControlFlow::Continue(()))
-            }
-            _ => Err("invalid frame".into()),
+    #[allow(unused_variables)]
+    fn reject_frame(&mut self, reason: RejectReason, raw: &[u8]) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_reject(reason);
+        }
+        #[cfg(feature = "reject-hook")]
+        if let Some(hook) = &mut self.on_rejected_frame {
+            hook(reason, raw);
+        }
+    }
+
+    /// Registers a hook that is invoked with the raw bytes of every inbound frame
+    /// that is rejected or dropped, useful for inspecting misbehaving clients.
+    #[cfg(feature = "reject-hook")]
+    pub fn on_rejected_frame<F>(&mut self, hook: F)
+    where
+        F: FnMut(RejectReason, &[u8]) + Send + 'static,
+    {
+        self.on_rejected_frame = Some(Box::new(hook));
+    }
+}
+
+/// Frame layout of `Notify` and `Request` frames, excluding the op code and id.
+struct Header {
+    trace_len: u8,
+    method_offset: u32,
+    method_len: u16,
+    method_utf8: bool,
+    data_offset: u32,
+    deadline: Option<Duration>,
+}
+
+enum Frame {
+    Notify(Header),
+    Call(u32, Header),
+    Reset(u32),
+    Response(u32),
+    Error(u32),
+    Chunk(u32),
+    Heartbeat,
+}
+
+fn parse_frame(buf: &[u8], features: Features, binary_names: bool) -> Result<Frame, FrameError> {
+    let reader = &mut &buf[..];
+    let frame_type = get_slice(reader, 1).ok_or(FrameError::Empty)?[0];
+    let traced = frame_type & TRACE_FLAG != 0;
+    let wide = frame_type & WIDE_NAME_FLAG != 0;
+    let has_deadline = frame_type & DEADLINE_FLAG != 0;
+    if (traced && !features.contains(Features::TRACE_CONTEXT))
+        || (wide && !features.contains(Features::WIDE_METHOD_NAMES))
+        || (has_deadline && !features.contains(Features::DEADLINES))
+    {
+        return Err(FrameError::UnknownType(frame_type));
+    }
+
+    let parse_header = |reader: &mut &[u8]| -> Result<Header, FrameError> {
+        let trace_len = if traced {
+            parse_trace_context(reader)?
+        } else {
+            0
+        };
+        let (method_len, method_utf8) = parse_rpc_name(reader, wide, binary_names)?;
+        let data_offset = (buf.len() - reader.len()) as u32;
+        Ok(Header {
+            trace_len,
+            method_offset: data_offset - method_len as u32,
+            method_len,
+            method_utf8,
+            data_offset,
+            deadline: None,
+        })
+    };
+
+    let flags = frame_type & (TRACE_FLAG | WIDE_NAME_FLAG | DEADLINE_FLAG);
+    match frame_type & !(TRACE_FLAG | WIDE_NAME_FLAG | DEADLINE_FLAG) {
+        1 if !has_deadline => Ok(Frame::Notify(parse_header(reader)?)),
+        2 => {
+            let id = parse_rpc_id(reader)?;
+            let deadline = match has_deadline {
+                true => Some(parse_deadline(reader)?),
+                false => None,
+            };
+            let header = Header {
+                deadline,
+                ..parse_header(reader)?
+            };
+            Ok(Frame::Call(id, header))
         }
+        3 if flags == 0 => Ok(Frame::Reset(parse_rpc_id(reader)?)),
+        4 if flags == 0 => Ok(Frame::Response(parse_rpc_id(reader)?)),
+        5 if flags == 0 => Ok(Frame::Error(parse_rpc_id(reader)?)),
+        6 if flags == 0 => Ok(Frame::Chunk(parse_rpc_id(reader)?)),
+        7 if flags == 0 => Ok(Frame::Heartbeat),
+        _ => Err(FrameError::UnknownType(frame_type)),
     }
 }
 
+/// Set once the peer resets the rpc.
+const RESET: usize = 1;
+/// Set once a reply (response or error frame) was sent for the rpc.
+const RESPONDED: usize = 2;
+
 struct ResetInner {
-    is_reset: bool,
-    // todo: use `AtomicUsize` as state for both `is_reset` and `has_waker`
-    // todo: use spinlock using `AtomicUsize` state ?
-    waker: Option<std::task::Waker>,
+    state: AtomicUsize,
+    waker: AtomicWaker,
+    /// Created on first use, by either `AbortController::cancellation_token` or the reset.
+    #[cfg(feature = "tokio-util")]
+    token: std::sync::OnceLock<tokio_util::sync::CancellationToken>,
+    /// The task that expires the rpc at its deadline, aborted once it's responded.
+    deadline: Mutex<Option<AbortHandle>>,
 }
 
 impl ResetInner {
     fn new() -> Self {
         Self {
-            is_reset: false,
-            waker: None,
+            state: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            #[cfg(feature = "tokio-util")]
+            token: std::sync::OnceLock::new(),
+            deadline: Mutex::new(None),
         }
     }
 
-    fn reset(&mut self) {
-        self.is_reset = true;
-        if let Some(waker) = &self.waker {
-            waker.wake_by_ref();
+    #[cfg(feature = "tokio-util")]
+    fn token(&self) -> &tokio_util::sync::CancellationToken {
+        self.token.get_or_init(Default::default)
+    }
+
+    fn is_reset(&self) -> bool {
+        self.state.load(Ordering::Acquire) & RESET != 0
+    }
+
+    fn reset(&self) {
+        self.cancel_deadline();
+        self.state.fetch_or(RESET, Ordering::AcqRel);
+        self.waker.wake();
+        #[cfg(feature = "tokio-util")]
+        self.token().cancel();
+    }
+
+    /// Resets the rpc once its deadline passed, returns `true` if it was neither reset by
+    /// the caller nor responded yet, i.e. the caller should be sent an error reply.
+    ///
+    /// Called by the deadline task, which must not be aborted while sending that reply.
+    fn expire(&self) -> bool {
+        lock(&self.deadline).take();
+        let state = self.state.fetch_or(RESET | RESPONDED, Ordering::AcqRel);
+        self.waker.wake();
+        #[cfg(feature = "tokio-util")]
+        self.token().cancel();
+        state & (RESET | RESPONDED) == 0
+    }
+
+    /// Resets the rpc on this side, returns `true` if it was neither reset by the caller
+    /// nor responded yet, i.e. the caller should be sent an error reply.
+    fn abort(&self) -> bool {
+        let state = self.state.fetch_or(RESET | RESPONDED, Ordering::AcqRel);
+        self.waker.wake();
+        #[cfg(feature = "tokio-util")]
+        self.token().cancel();
+        state & (RESET | RESPONDED) == 0
+    }
+
+    /// Marks the rpc as responded, returns the previous state.
+    fn respond(&self) -> usize {
+        self.cancel_deadline();
+        self.state.fetch_or(RESPONDED, Ordering::AcqRel)
+    }
+
+    fn cancel_deadline(&self) {
+        if let Some(task) = lock(&self.deadline).take() {
+            task.abort();
         }
     }
 }
 
-type ResetShared = Arc<Mutex<ResetInner>>;
+impl Drop for ResetInner {
+    fn drop(&mut self) {
+        self.cancel_deadline();
+    }
+}
+
+type ResetShared = Arc<ResetInner>;
 
 /// `AbortController` is a controller that allows you to monitor for a stream reset and
 /// cancel an associated asynchronous task if the reset occurs.
+///
+/// Dropping the paired [`Response`] without sending a reply also counts as a reset.
 pub struct AbortController {
     inner: ResetShared,
 }
@@ -276,22 +2159,34 @@ pub struct AbortController {
 impl AbortController {
     pub(crate) fn new() -> Self {
         Self {
-            inner: Arc::new(Mutex::new(ResetInner::new())),
+            inner: Arc::new(ResetInner::new()),
         }
     }
 
+    /// Returns a `CancellationToken` that is cancelled once the client resets this rpc,
+    /// to pass the reset into existing cancellation-aware code.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let rows = db.query(sql, ctl.cancellation_token()).await?;
+    /// ```
+    #[cfg(feature = "tokio-util")]
+    pub fn cancellation_token(&self) -> tokio_util::sync::CancellationToken {
+        self.inner.token().clone()
+    }
+
     /// Polls to be notified when the client resets this rpc.
     /// If the stream has not been reset. This returns `Poll::Pending`
     pub fn poll_reset(&mut self, cx: &mut Context<'_>) -> Poll<()> {
-        let mut inner = self.inner.lock().unwrap();
-        if inner.is_reset {
+        if self.inner.is_reset() {
             return Poll::Ready(());
         }
-        match inner.waker.as_mut() {
-            Some(w) => w.clone_from(cx.waker()),
-            None => inner.waker = Some(cx.waker().clone()),
+        self.inner.waker.register(cx.waker());
+        // Checked again, in case the reset happened before the waker was registered.
+        if self.inner.is_reset() {
+            return Poll::Ready(());
         }
-        drop(inner);
         Poll::Pending
     }
 
@@ -318,11 +2213,70 @@ impl AbortController {
         .await;
     }
 
+    /// Like [`AbortController::abort_on_reset`], but if the stream is reset, `task` is dropped
+    /// and then `on_reset` is awaited, e.g. to release resources or notify other clients.
+    ///
+    /// `on_reset` is never polled if `task` completes first.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// controller
+    ///     .abort_on_reset_with(async { ... }, async { notifier.notify("cancelled", id).await })
+    ///     .await;
+    /// ```
+    pub async fn abort_on_reset_with(mut self, task: impl Future, on_reset: impl Future) {
+        let is_reset = {
+            let mut task = std::pin::pin!(task);
+            std::future::poll_fn(|cx| {
+                if let Poll::Ready(()) = self.poll_reset(cx) {
+                    return Poll::Ready(true);
+                }
+                task.as_mut().poll(cx).map(|_| false)
+            })
+            .await
+        };
+        if is_reset {
+            on_reset.await;
+        }
+    }
+
+    /// Like [`AbortController::poll_reset`], but also returns `Poll::Ready` once
+    /// `sleep` has elapsed.
+    pub fn poll_timeout(&mut self, cx: &mut Context<'_>, sleep: Pin<&mut Sleep>) -> Poll<()> {
+        if let Poll::Ready(()) = self.poll_reset(cx) {
+            return Poll::Ready(());
+        }
+        sleep.poll(cx)
+    }
+
+    /// Executes a given asynchronous task and aborts it when stream is reset,
+    /// or when it runs longer than `timeout`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// controller.abort_on_reset_or_timeout(Duration::from_secs(5), async {  }).await;
+    /// ```
+    pub async fn abort_on_reset_or_timeout(mut self, timeout: Duration, task: impl Future) {
+        let mut task = std::pin::pin!(task);
+        let mut sleep = std::pin::pin!(tokio::time::sleep(timeout));
+        std::future::poll_fn(|cx| {
+            if let Poll::Ready(()) = self.poll_timeout(cx, sleep.as_mut()) {
+                return Poll::Ready(());
+            }
+            task.as_mut().poll(cx).map(|_| ())
+        })
+        .await;
+    }
+
     /// Spawns a new task that will be aborted if the stream is reset.
     ///
     /// This function spawns the given task in background, and automatically cancels
     /// the task if the stream reset event occurs.
     ///
+    /// If the task panics, the panic is propagated to the returned `JoinHandle`.
+    ///
     /// ### Example
     ///
     /// ```rust
@@ -340,86 +2294,421 @@ impl AbortController {
 #[derive(Debug)]
 pub struct Request {
     buf: Box<[u8]>,
-    method_offset: u8,
-    method_len: u8,
-    data_offset: u16,
+    trace_len: u8,
+    method_offset: u32,
+    method_len: u16,
+    method_utf8: bool,
+    data_offset: u32,
+    streamed: bool,
+    deadline: Option<Duration>,
 }
 
 /// Represents a response used to send the result of a rpc request.
+///
+/// A `Response` dropped without sending a reply (e.g. the handler returned early or
+/// panicked) sends an error frame, `"no response"` or `"handler panicked"`, so that
+/// the caller's pending call fails instead of hanging, even if the reply channel is full
+/// at that point. Without [`Features::ERROR_FRAMES`]
+/// (e.g. over [`PROTOCOL_V1`]), where a single call can't be failed, the connection is
+/// closed with code `1011` and that reason instead. Nothing is sent for a call that
+/// the caller has reset.
 pub struct Response {
     id: u32,
     tx: Sender<Reply>,
     resetter: Resetter,
+    reset: ResetShared,
+    features: Features,
 }
 
 impl Drop for Response {
     fn drop(&mut self) {
-        self.resetter.lock().unwrap().remove(&self.id);
+        self.resetter.remove(self.id, &self.reset);
+
+        // Dropped without a reply (e.g. the handler panicked or returned early),
+        // send an error frame so that the pending call fails instead of hanging,
+        // and stop the tasks of the paired `AbortController`, as nobody can reply anymore.
+        if self.reset.respond() & (RESET | RESPONDED) == 0 {
+            self.reset.reset();
+
+            let reason = if std::thread::panicking() {
+                "handler panicked"
+            } else {
+                "no response"
+            };
+            send_detached(&self.tx, call_failure(self.features, self.id, 1011, reason));
+        }
     }
 }
 
 /// Represents rpc response.
 impl Response {
+    /// Number of bytes reserved at the start of the buffer passed to [`Response::send_bytes`].
+    pub const HEADER_LEN: usize = 5;
+
     /// Returns the ID of the rpc request.
     #[inline]
     pub fn id(&self) -> u32 {
         self.id
     }
 
+    /// Returns `true` if the connection's writer has stopped, so the response can't be delivered.
+    ///
+    /// Unlike [`AbortController`], which observes the caller resetting this call,
+    /// this observes the whole connection going away.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
     /// Sends the response with the provided data.
-    pub async fn send(self, data: impl AsRef<[u8]>) -> Result<(), ReceiverClosed> {
-        let data = data.as_ref();
-        let mut buf = Vec::with_capacity(5 + data.len());
+    ///
+    /// Fails with [`ResponseError::Reset`] if the caller has reset the call.
+    pub async fn send(self, data: impl AsRef<[u8]>) -> Result<(), ResponseError> {
+        self.reply(encode_response(4, self.id, data.as_ref())).await
+    }
 
-        buf.push(4); // frame type
-        buf.extend_from_slice(&self.id.to_be_bytes()); // call id
-        buf.extend_from_slice(data);
+    /// Sends an error reply, the caller's pending call fails with the provided data.
+    ///
+    /// On the server side of [`SocketIo::call`], this surfaces as an `io::Error`
+    /// wrapping [`RemoteError`].
+    ///
+    /// Fails with [`ResponseError::Unsupported`] if the peer doesn't support
+    /// [`Features::ERROR_FRAMES`], e.g. over [`PROTOCOL_V1`].
+    pub async fn send_error(self, data: impl AsRef<[u8]>) -> Result<(), ResponseError> {
+        self.require(Features::ERROR_FRAMES)?;
+        self.reply(encode_response(5, self.id, data.as_ref())).await
+    }
 
-        self.tx
-            .send(Reply::Response(buf.into()))
+    /// Sends the response, then closes the connection with the provided code and reason,
+    /// e.g. for one-shot rpc endpoints.
+    ///
+    /// The response is flushed before the close frame is written. The connection is closed
+    /// even if the caller has reset the call, in which case [`ResponseError::Reset`] is returned.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// res.send_and_close(b"done", 1000, "bye").await?;
+    /// ```
+    pub async fn send_and_close(
+        self,
+        data: impl AsRef<[u8]>,
+        code: u16,
+        reason: &str,
+    ) -> Result<(), ResponseError> {
+        let result = self.reply(encode_response(4, self.id, data.as_ref())).await;
+
+        let (ack, closed) = oneshot::channel();
+        if self
+            .tx
+            .send(Reply::Close(code, reason.into(), ack))
+            .await
+            .is_ok()
+        {
+            let _ = closed.await;
+        }
+        result
+    }
+
+    /// Sends the response without copying the payload.
+    ///
+    /// The first [`Response::HEADER_LEN`] bytes of `buf` are reserved for the frame header,
+    /// the rest of the buffer is the payload. If `buf` isn't shared (no clones of it are
+    /// alive), the header is written in place. Build the buffer with the headroom up front
+    /// to take this fast path:
+    ///
+    /// ```rust
+    /// let payload = b"pong";
+    /// let mut buf = BytesMut::with_capacity(Response::HEADER_LEN + payload.len());
+    /// buf.put_bytes(0, Response::HEADER_LEN);
+    /// buf.extend_from_slice(payload);
+    /// res.send_bytes(buf.freeze()).await?;
+    /// ```
+    ///
+    /// Otherwise the payload is copied into a new frame, like [`Response::send`].
+    /// The first `HEADER_LEN` bytes are never sent, on either path. A buffer shorter than
+    /// that fails with [`ResponseError::MissingHeader`], and the call is answered as if
+    /// the `Response` was dropped.
+    pub async fn send_bytes(self, buf: Bytes) -> Result<(), ResponseError> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(ResponseError::MissingHeader);
+        }
+        let frame = match buf.try_into_mut() {
+            Ok(mut buf) => {
+                let mut header = &mut buf[..Self::HEADER_LEN];
+                header.put_u8(4); // frame type
+                header.put_u32(self.id); // call id
+                buf.freeze()
+            }
+            Err(buf) => encode_response(4, self.id, &buf[Self::HEADER_LEN..]),
+        };
+        self.reply(frame).await
+    }
+
+    /// Fails the call with `reason`, like [`Response::send_error`], but without
+    /// [`Features::ERROR_FRAMES`] the connection is closed with `code` instead.
+    pub(crate) async fn fail(self, code: u16, reason: &str) {
+        if self.reset.respond() & RESET != 0 {
+            return;
+        }
+        let _ = self
+            .tx
+            .send(call_failure(self.features, self.id, code, reason))
+            .await;
+    }
+
+    fn require(&self, feature: Features) -> Result<(), ResponseError> {
+        match self.features.contains(feature) {
+            true => Ok(()),
+            false => Err(ResponseError::Unsupported(feature)),
+        }
+    }
+
+    async fn reply(&self, frame: Bytes) -> Result<(), ResponseError> {
+        if self.reset.respond() & RESET != 0 {
+            // The caller has already given up on this call, don't send a stale frame.
+            return Err(ResponseError::Reset);
+        }
+        #[cfg(feature = "tracing")]
+        let (is_error, len) = (frame[0] == 5, frame.len() - Self::HEADER_LEN);
+
+        let result = self
+            .tx
+            .send(Reply::Response(frame))
             .await
-            .map_err(|_| ReceiverClosed)
+            .map_err(|_| ResponseError::ReceiverClosed);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            id = self.id,
+            is_error,
+            len,
+            ok = result.is_ok(),
+            "sent response"
+        );
+        result
+    }
+}
+
+/// Fails the peer's call `id` with `reason`, sent as an `Error` frame.
+///
+/// Without [`Features::ERROR_FRAMES`] (e.g. over [`PROTOCOL_V1`]) a single call can't be
+/// failed, the connection is closed with `code` and `reason` instead, so that the caller
+/// doesn't wait for a reply forever.
+fn call_failure(features: Features, id: u32, code: u16, reason: &str) -> Reply {
+    match features.contains(Features::ERROR_FRAMES) {
+        true => Reply::Response(encode_response(5, id, reason.as_bytes())),
+        false => {
+            let (ack, _) = oneshot::channel();
+            Reply::Close(code, reason.into(), ack)
+        }
+    }
+}
+
+/// Queues `reply` without waiting, e.g. from `Drop`.
+///
+/// If the channel is full, the reply is sent by a spawned task once there's room,
+/// instead of being lost.
+fn send_detached(tx: &Sender<Reply>, reply: Reply) {
+    if let Err(TrySendError::Full(reply)) = tx.try_send(reply) {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let tx = tx.clone();
+            runtime.spawn(async move { tx.send(reply).await });
+        }
     }
 }
 
+fn encode_response(frame_type: u8, id: u32, data: &[u8]) -> Bytes {
+    let mut buf = Vec::with_capacity(5 + data.len());
+
+    buf.push(frame_type);
+    buf.extend_from_slice(&id.to_be_bytes()); // call id
+    buf.extend_from_slice(data);
+    buf.into()
+}
+
 impl Request {
+    fn new(buf: Box<[u8]>, header: Header, streamed: bool) -> Self {
+        Self {
+            buf,
+            trace_len: header.trace_len,
+            method_offset: header.method_offset,
+            method_len: header.method_len,
+            method_utf8: header.method_utf8,
+            data_offset: header.data_offset,
+            streamed,
+            deadline: header.deadline,
+        }
+    }
+
     /// Returns the rpc method name.
+    ///
+    /// Returns an empty string if the name isn't valid UTF-8, which is only possible with
+    /// [`SocketIoBuilder::binary_method_names`], see [`Request::try_method`].
     #[inline]
     pub fn method(&self) -> &str {
-        unsafe {
-            let offset = self.method_offset as usize;
-            let length = self.method_len as usize;
-            std::str::from_utf8_unchecked(&self.buf.get_unchecked(offset..(offset + length)))
+        if !self.method_utf8 {
+            return "";
+        }
+        let method = self.method_bytes();
+        debug_assert!(std::str::from_utf8(method).is_ok());
+        // SAFETY: `method_utf8` is only set if `parse_frame` validated the name as UTF-8.
+        unsafe { std::str::from_utf8_unchecked(method) }
+    }
+
+    /// Returns the rpc method name, or `None` if it isn't valid UTF-8.
+    #[inline]
+    pub fn try_method(&self) -> Option<&str> {
+        self.method_utf8.then(|| self.method())
+    }
+
+    /// Returns the raw bytes of the rpc method name, e.g. binary method ids.
+    #[inline]
+    pub fn method_bytes(&self) -> &[u8] {
+        let offset = self.method_offset as usize;
+        &self.buf[offset..offset + self.method_len as usize]
+    }
+
+    /// Returns the trace context (e.g. W3C `traceparent` bytes) attached by the peer, if any.
+    ///
+    /// Only set if trace contexts were negotiated, see [`Features::TRACE_CONTEXT`].
+    pub fn trace_context(&self) -> Option<&[u8]> {
+        if self.trace_len == 0 {
+            return None;
         }
+        // trace context is followed by the method name length prefix.
+        let prefix_len = if self.buf[0] & WIDE_NAME_FLAG != 0 {
+            2
+        } else {
+            1
+        };
+        let end = self.method_offset as usize - prefix_len;
+        Some(&self.buf[end - self.trace_len as usize..end])
     }
 
     /// Returns the data payload of the request.
     #[inline]
     pub fn data(&self) -> &[u8] {
-        &self.buf[self.data_offset.into()..]
+        &self.buf[self.data_offset as usize..]
+    }
+
+    /// Returns the length of the data payload in bytes.
+    #[inline]
+    pub fn data_len(&self) -> usize {
+        self.buf.len() - self.data_offset as usize
     }
+
+    /// Returns `true` if the data payload is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data_len() == 0
+    }
+
+    /// Returns `true` if the request arrived as a fragmented WebSocket message,
+    /// e.g. to stream the reply to a streamed request.
+    ///
+    /// Always `true` for the request of a [`Procedure::StreamCall`].
+    #[inline]
+    pub fn was_streamed(&self) -> bool {
+        self.streamed
+    }
+
+    /// Returns the deadline attached by the caller, relative to when the request was received.
+    ///
+    /// Once it passes, the paired [`AbortController`] is reset and the caller is sent
+    /// a `"deadline exceeded"` error, a late [`Response::send`] fails with [`ResponseError::Reset`].
+    /// Only set if deadlines were negotiated, see [`Features::DEADLINES`].
+    #[inline]
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Converts the request into its data payload, without copying.
+    ///
+    /// The returned `Bytes` can be cheaply cloned and shared across tasks.
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from(self.buf).slice(self.data_offset as usize..)
+    }
+
+    /// Returns the method name and the data payload, see [`Request::method`] and [`Request::data`].
+    #[inline]
+    pub fn parts(&self) -> (&str, &[u8]) {
+        (self.method(), self.data())
+    }
+
+    /// Converts the request into an owned method name and data payload.
+    pub fn into_owned(self) -> (String, Vec<u8>) {
+        (self.method().into(), self.data().into())
+    }
+}
+
+/// Bounds a single write to the underlying stream by the configured write timeout.
+async fn timed(
+    timeout: Option<Duration>,
+    write: impl Future<Output = io::Result<()>>,
+) -> io::Result<()> {
+    match timeout {
+        Some(dur) => tokio::time::timeout(dur, write)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+        None => write.await,
+    }
+}
+
+/// Locks the mutex, ignoring poisoning.
+///
+/// The guarded state is always left consistent, so a panic elsewhere
+/// while holding the lock must not take down the whole connection.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-fn parse_rpc_id(reader: &mut &[u8]) -> Result<u32, &'static str> {
-    let raw_id = get_slice(reader, 4)?;
+fn parse_rpc_id(reader: &mut &[u8]) -> Result<u32, FrameError> {
+    let raw_id = get_slice(reader, 4).ok_or(FrameError::TruncatedId)?;
     let id = u32::from_be_bytes(raw_id.try_into().unwrap());
     Ok(id)
 }
 
-fn validate_and_parse_utf8_rpc_name(reader: &mut &[u8]) -> Result<u8, DynErr> {
-    let method_len = get_slice(reader, 1)?[0];
-    std::str::from_utf8(get_slice(reader, method_len as usize)?)?;
-    Ok(method_len)
+/// Parses the deadline of a `Request` frame, in milliseconds relative to its receipt.
+fn parse_deadline(reader: &mut &[u8]) -> Result<Duration, FrameError> {
+    let raw = get_slice(reader, 4).ok_or(FrameError::TruncatedDeadline)?;
+    let millis = u32::from_be_bytes(raw.try_into().unwrap());
+    Ok(Duration::from_millis(millis.into()))
+}
+
+fn parse_trace_context(reader: &mut &[u8]) -> Result<u8, FrameError> {
+    let trace_len = get_slice(reader, 1).ok_or(FrameError::TruncatedTraceContext)?[0];
+    get_slice(reader, trace_len as usize).ok_or(FrameError::TruncatedTraceContext)?;
+    Ok(trace_len)
+}
+
+/// Returns the length of the method name, and whether it's valid UTF-8.
+///
+/// Non UTF-8 names are rejected, unless `binary` is set.
+fn parse_rpc_name(reader: &mut &[u8], wide: bool, binary: bool) -> Result<(u16, bool), FrameError> {
+    let method_len = if wide {
+        let raw_len = get_slice(reader, 2).ok_or(FrameError::TruncatedMethodLen)?;
+        u16::from_be_bytes(raw_len.try_into().unwrap())
+    } else {
+        get_slice(reader, 1).ok_or(FrameError::TruncatedMethodLen)?[0].into()
+    };
+    let method = get_slice(reader, method_len as usize).ok_or(FrameError::TruncatedMethod)?;
+    let is_utf8 = match std::str::from_utf8(method) {
+        Ok(_) => true,
+        Err(_) if binary => false,
+        Err(_) => return Err(FrameError::InvalidUtf8Method),
+    };
+    Ok((method_len, is_utf8))
 }
 
-fn get_slice<'de>(reader: &mut &'de [u8], len: usize) -> Result<&'de [u8], &'static str> {
+fn get_slice<'de>(reader: &mut &'de [u8], len: usize) -> Option<&'de [u8]> {
     if len <= reader.len() {
         unsafe {
             let slice = reader.get_unchecked(..len);
             *reader = reader.get_unchecked(len..);
-            Ok(slice)
+            Some(slice)
         }
     } else {
-        Err("insufficient bytes")
+        None
     }
 }
@@ -0,0 +1,52 @@
+use crate::{error::RejectReason, ConnId};
+use std::io;
+
+/// Hooks for collecting connection metrics, e.g. Prometheus counters.
+///
+/// Every method has a no-op default, so implementors only override what they count.
+/// Register an implementation with [`SocketIoBuilder::metrics`](crate::SocketIoBuilder::metrics),
+/// a single instance can be shared by many connections.
+///
+/// ### Example
+///
+/// ```rust
+/// struct Counters { calls: AtomicU64 }
+///
+/// impl Metrics for Counters {
+///     fn on_call(&self, _method: &str) {
+///         self.calls.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+/// ```
+pub trait Metrics: Send + Sync {
+    /// Invoked for every rpc call received, before it's yielded by [`SocketIo::recv`](crate::SocketIo::recv).
+    fn on_call(&self, method: &str) {
+        let _ = method;
+    }
+
+    /// Invoked for every notification received.
+    fn on_notify(&self, method: &str) {
+        let _ = method;
+    }
+
+    /// Invoked when the peer resets an in-flight rpc call.
+    fn on_reset(&self, id: u32) {
+        let _ = id;
+    }
+
+    /// Invoked when an inbound frame is rejected or dropped.
+    fn on_reject(&self, reason: RejectReason) {
+        let _ = reason;
+    }
+
+    /// Invoked when [`Notifier::try_notify`](crate::Notifier::try_notify) fails with
+    /// `TryNotifyError::Full`, i.e. the connection `conn` isn't keeping up.
+    fn on_backpressure(&self, conn: ConnId) {
+        let _ = conn;
+    }
+
+    /// Invoked with every error returned by [`SocketIo::recv`](crate::SocketIo::recv).
+    fn on_error(&self, err: &io::Error) {
+        let _ = err;
+    }
+}
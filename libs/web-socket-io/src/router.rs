@@ -0,0 +1,201 @@
+use crate::{error::is_fatal, AbortController, Body, Procedure, Request, Response, SocketIo};
+use std::{collections::HashMap, future::Future, io, pin::Pin};
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type CallHandler<S> = Box<dyn Fn(S, Request, Response, AbortController) -> BoxFuture + Send + Sync>;
+type StreamCallHandler<S> =
+    Box<dyn Fn(S, Request, Body, Response, AbortController) -> BoxFuture + Send + Sync>;
+type NotifyHandler<S> = Box<dyn Fn(S, Request) -> BoxFuture + Send + Sync>;
+
+/// Dispatches incoming procedures to handlers registered by method name.
+///
+/// Every handler receives a clone of the shared state `S`, and runs on its own task.
+/// Wrap the router in an `Arc` to serve many connections.
+///
+/// A rpc call is dispatched to the handler registered for its exact method name,
+/// otherwise to the handler of the longest matching prefix, see [`Router::call_prefix`].
+///
+/// ### Example
+///
+/// ```rust
+/// let router = Router::with_state(db)
+///     .call("get_user", |db, req, res, _| async move {
+///         res.send(db.get_user(req.data()).await).await;
+///     })
+///     .notify("typing", |db, req| async move { ... });
+///
+/// let err = router.serve(socket).await;
+/// ```
+pub struct Router<S = ()> {
+    state: S,
+    calls: HashMap<Box<str>, CallHandler<S>>,
+    /// Sorted by descending prefix length, so that the longest match is found first.
+    call_prefixes: Vec<(Box<str>, CallHandler<S>)>,
+    stream_calls: HashMap<Box<str>, StreamCallHandler<S>>,
+    notifications: HashMap<Box<str>, NotifyHandler<S>>,
+}
+
+impl Router {
+    /// Creates an empty `Router` without state.
+    pub fn new() -> Self {
+        Self::with_state(())
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Router<S>
+where
+    S: Clone + Send + 'static,
+{
+    /// Creates an empty `Router`, that passes `state` to every handler.
+    pub fn with_state(state: S) -> Self {
+        Self {
+            state,
+            calls: HashMap::new(),
+            call_prefixes: Vec::new(),
+            stream_calls: HashMap::new(),
+            notifications: HashMap::new(),
+        }
+    }
+
+    /// Registers a handler for the rpc method `name`.
+    pub fn call<F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(S, Request, Response, AbortController) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.calls.insert(
+            name.into(),
+            Box::new(move |state, req, res, c| Box::pin(handler(state, req, res, c))),
+        );
+        self
+    }
+
+    /// Registers a handler for every rpc method starting with `prefix` (e.g. `"admin."`),
+    /// that has no handler registered for its exact name.
+    ///
+    /// If several prefixes match, the longest one wins.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let router = Router::new()
+    ///     .call_prefix("admin.", |_, req, res, _| async move { ... })
+    ///     .call("admin.reset", |_, req, res, _| async move { ... });
+    /// ```
+    pub fn call_prefix<F, Fut>(mut self, prefix: &str, handler: F) -> Self
+    where
+        F: Fn(S, Request, Response, AbortController) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler: CallHandler<S> =
+            Box::new(move |state, req, res, c| Box::pin(handler(state, req, res, c)));
+
+        match self.call_prefixes.iter().position(|(p, _)| **p == *prefix) {
+            Some(i) => self.call_prefixes[i].1 = handler,
+            None => {
+                let i = self
+                    .call_prefixes
+                    .partition_point(|(p, _)| p.len() >= prefix.len());
+                self.call_prefixes.insert(i, (prefix.into(), handler));
+            }
+        }
+        self
+    }
+
+    fn find_call(&self, method: &str) -> Option<&CallHandler<S>> {
+        self.calls.get(method).or_else(|| {
+            self.call_prefixes
+                .iter()
+                .find(|(prefix, _)| method.starts_with(&**prefix))
+                .map(|(_, handler)| handler)
+        })
+    }
+
+    /// Registers a handler for the rpc method `name`, that is called with the [`Body`]
+    /// of a [`Procedure::StreamCall`], see [`SocketIo::stream_calls`].
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let router = Router::new().stream_call("upload", |_, req, mut body, res, _| async move {
+    ///     let len = tokio::io::copy(&mut body, &mut file).await?;
+    ///     res.send(len.to_string()).await;
+    /// });
+    /// ```
+    pub fn stream_call<F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(S, Request, Body, Response, AbortController) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.stream_calls.insert(
+            name.into(),
+            Box::new(move |state, req, body, res, c| Box::pin(handler(state, req, body, res, c))),
+        );
+        self
+    }
+
+    /// Registers a handler for the notification `name`.
+    pub fn notify<F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(S, Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.notifications.insert(
+            name.into(),
+            Box::new(move |state, req| Box::pin(handler(state, req))),
+        );
+        self
+    }
+
+    /// Receives procedures from `socket` and dispatches them, until the connection fails.
+    ///
+    /// Calls to an unregistered method are answered with an error frame, notifications
+    /// are ignored. A streamed call to a method that only has a [`Router::call`] handler is
+    /// answered with an error frame too. Without [`Features::ERROR_FRAMES`](crate::Features::ERROR_FRAMES)
+    /// (e.g. over [`PROTOCOL_V1`](crate::PROTOCOL_V1)), such a call closes the connection
+    /// with code `1008` instead. Returns the error that ended the connection.
+    pub async fn serve<T>(&self, mut socket: SocketIo<T>) -> io::Error {
+        loop {
+            let procedure = match socket.recv().await {
+                Ok(procedure) => procedure,
+                Err(err) if is_fatal(&err) => return err,
+                Err(_) => continue,
+            };
+            match procedure {
+                Procedure::Call(req, res, c) => match self.find_call(req.method()) {
+                    Some(handler) => {
+                        tokio::spawn(handler(self.state.clone(), req, res, c));
+                    }
+                    None => {
+                        res.fail(1008, "unknown method").await;
+                    }
+                },
+                Procedure::Notify(req) => {
+                    if let Some(handler) = self.notifications.get(req.method()) {
+                        tokio::spawn(handler(self.state.clone(), req));
+                    }
+                }
+                Procedure::StreamCall(req, body, res, c) => {
+                    match self.stream_calls.get(req.method()) {
+                        Some(handler) => {
+                            tokio::spawn(handler(self.state.clone(), req, body, res, c));
+                        }
+                        None if self.find_call(req.method()).is_some() => {
+                            res.fail(1008, "streamed calls aren't supported").await;
+                        }
+                        None => {
+                            res.fail(1008, "unknown method").await;
+                        }
+                    }
+                }
+                Procedure::Heartbeat(_) => {}
+            }
+        }
+    }
+}
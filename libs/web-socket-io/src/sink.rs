@@ -0,0 +1,131 @@
+use crate::{encode_response, error::ResponseError, CallGuard, Features, Reply, Response};
+use futures_core::Stream;
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+/// `None` marks the end of the response, an error ends it too.
+pub(crate) type ChunkSender = Sender<Option<io::Result<Vec<u8>>>>;
+
+/// Streams the result of a rpc call as multiple chunks, created by [`Response::into_sink`].
+///
+/// Every chunk is sent as a `Chunk` frame, and the caller concatenates them with the
+/// payload of the final response. Dropping the sink without ending it fails the call.
+pub struct ResponseSink {
+    res: Response,
+}
+
+impl Response {
+    /// Converts the response into a [`ResponseSink`], to send the result progressively.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let mut sink = res.into_sink();
+    /// while let Some(line) = logs.next().await {
+    ///     sink.send(line).await?;
+    /// }
+    /// sink.end("").await?;
+    /// ```
+    pub fn into_sink(self) -> ResponseSink {
+        ResponseSink { res: self }
+    }
+}
+
+impl ResponseSink {
+    /// Returns the ID of the rpc request.
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.res.id
+    }
+
+    /// Sends a chunk of the result.
+    ///
+    /// Fails with [`ResponseError::Reset`] once the caller has reset the call,
+    /// so that the producer can stop, and with [`ResponseError::Unsupported`] if the peer
+    /// doesn't support [`Features::CHUNKED_RESPONSES`].
+    pub async fn send(&mut self, chunk: impl AsRef<[u8]>) -> Result<(), ResponseError> {
+        self.res.require(Features::CHUNKED_RESPONSES)?;
+        if self.res.reset.is_reset() {
+            return Err(ResponseError::Reset);
+        }
+        self.res
+            .tx
+            .send(Reply::Response(encode_response(
+                6,
+                self.res.id,
+                chunk.as_ref(),
+            )))
+            .await
+            .map_err(|_| ResponseError::ReceiverClosed)
+    }
+
+    /// Sends the last chunk, and completes the call.
+    pub async fn end(self, data: impl AsRef<[u8]>) -> Result<(), ResponseError> {
+        self.res.send(data).await
+    }
+
+    /// Fails the call with an error reply, discarding the chunks sent so far.
+    pub async fn error(self, data: impl AsRef<[u8]>) -> Result<(), ResponseError> {
+        self.res.send_error(data).await
+    }
+}
+
+/// The chunks of a response, in the order they are received, created by
+/// [`SocketIo::call_chunked`](crate::SocketIo::call_chunked).
+///
+/// The payload of the final response (if not empty) is yielded as the last chunk.
+/// An error reply is yielded as an `io::Error` wrapping [`RemoteError`](crate::error::RemoteError),
+/// and `io::ErrorKind::ConnectionAborted` if the connection fails before the response ends.
+///
+/// Chunks are only received while [`SocketIo::recv`](crate::SocketIo::recv) is being polled,
+/// so the stream should be consumed on a separate task.
+pub struct ResponseChunks {
+    rx: Receiver<Option<io::Result<Vec<u8>>>>,
+    /// Resets the call, if the stream is dropped before the response ends.
+    guard: Option<CallGuard>,
+    is_done: bool,
+}
+
+impl ResponseChunks {
+    pub(crate) fn channel(buffer: usize) -> (ChunkSender, Self) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let chunks = Self {
+            rx,
+            guard: None,
+            is_done: false,
+        };
+        (tx, chunks)
+    }
+
+    pub(crate) fn guarded(mut self, guard: CallGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Returns the ID of the rpc call.
+    pub fn id(&self) -> Option<u32> {
+        self.guard.as_ref().map(|guard| guard.id)
+    }
+}
+
+impl Stream for ResponseChunks {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.is_done {
+            return Poll::Ready(None);
+        }
+        let item = match ready!(self.rx.poll_recv(cx)) {
+            Some(Some(Ok(chunk))) => return Poll::Ready(Some(Ok(chunk))),
+            Some(Some(Err(err))) => Some(Err(err)),
+            Some(None) => None,
+            None => Some(Err(io::ErrorKind::ConnectionAborted.into())),
+        };
+        self.is_done = true;
+        Poll::Ready(item)
+    }
+}
@@ -0,0 +1,98 @@
+use std::{future::Future, io, pin::Pin, time::Duration};
+use tokio::{io::AsyncRead, time::Instant};
+use web_socket::{Event, WebSocket};
+
+/// The read returned by [`FrameSource::recv`], handing the source back along with the event.
+pub type RecvFrame =
+    Pin<Box<dyn Future<Output = (Box<dyn FrameSource>, io::Result<Event>)> + Send>>;
+
+/// Reads the frames of a WebSocket connection, decoded into [`Event`]s.
+///
+/// Implemented for the `web_socket` reader used by [`SocketIo::new`](crate::SocketIo::new),
+/// and for the `fastwebsockets` reader used by `SocketIo::from_fastwebsockets` (with the
+/// `fastwebsockets` feature). Another WebSocket implementation can implement it, to be used
+/// with [`SocketIo::from_source`](crate::SocketIo::from_source), while replies are still
+/// written by `web_socket`.
+///
+/// The fragments of a message must be yielded as they arrive, as `DataType::Stream` events,
+/// and control frames as `Ping`, `Pong` and `Close` events.
+///
+/// The source is moved into the read, so that a read can outlive a dropped
+/// [`SocketIo::recv`](crate::SocketIo::recv) future and be resumed later.
+///
+/// ### Example
+///
+/// ```rust
+/// /// Events decoded elsewhere, e.g. by another task.
+/// struct ChannelSource(tokio::sync::mpsc::Receiver<Event>);
+///
+/// impl FrameSource for ChannelSource {
+///     fn recv(mut self: Box<Self>) -> RecvFrame {
+///         Box::pin(async move {
+///             let event = self.0.recv().await;
+///             let event = event.ok_or_else(|| io::ErrorKind::UnexpectedEof.into());
+///             (self as Box<dyn FrameSource>, event)
+///         })
+///     }
+/// }
+/// ```
+pub trait FrameSource: Send + 'static {
+    /// Receives the next event.
+    fn recv(self: Box<Self>) -> RecvFrame;
+}
+
+impl<R> FrameSource for WebSocket<R>
+where
+    R: Unpin + AsyncRead + Send + 'static,
+{
+    fn recv(mut self: Box<Self>) -> RecvFrame {
+        Box::pin(async move {
+            let event = WebSocket::recv(&mut *self).await;
+            (self as Box<dyn FrameSource>, event)
+        })
+    }
+}
+
+/// Owns the [`FrameSource`] of a `SocketIo`, keeping a pending read across dropped
+/// futures, so that [`SocketIo::recv`](crate::SocketIo::recv) is cancellation safe.
+pub(crate) enum FrameReader {
+    Idle(Box<dyn FrameSource>),
+    Reading(RecvFrame, Option<Instant>),
+    /// A read timed out, the connection is no longer read from.
+    TimedOut,
+}
+
+impl FrameReader {
+    pub(crate) fn new(source: Box<dyn FrameSource>) -> Self {
+        Self::Idle(source)
+    }
+
+    /// Receives the next event, or `None` once `timeout` elapses without one.
+    ///
+    /// The timeout starts with the read, so a read resumed by a later call keeps its deadline.
+    /// Only the call that times out returns `None`, later calls fail with `TimedOut` at once.
+    pub(crate) async fn recv(&mut self, timeout: Option<Duration>) -> Option<io::Result<Event>> {
+        if let Self::Idle(_) = self {
+            let Self::Idle(source) = std::mem::replace(self, Self::TimedOut) else {
+                unreachable!()
+            };
+            *self = Self::Reading(source.recv(), timeout.map(|dur| Instant::now() + dur));
+        }
+        let (read, deadline) = match self {
+            Self::Reading(read, deadline) => (read, *deadline),
+            _ => return Some(Err(io::ErrorKind::TimedOut.into())),
+        };
+        let (source, event) = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, read).await {
+                Ok(read) => read,
+                Err(_) => {
+                    *self = Self::TimedOut;
+                    return None;
+                }
+            },
+            None => read.await,
+        };
+        *self = Self::Idle(source);
+        Some(event)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{Notifier, Procedure, SocketIo};
+use std::{future::Future, io};
+
+/// The receiving half of a [`SocketIo`], created by [`SocketIo::split`].
+///
+/// Notifications and responses are sent through the shared writer, so the
+/// `Notifier` half can be used on another task while this half keeps receiving.
+pub struct SocketIoReceiver<S = ()> {
+    socket: SocketIo<S>,
+}
+
+impl<S> SocketIo<S> {
+    /// Splits the `SocketIo` into a receiving half and a `Notifier`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let (mut receiver, notifier) = socket.split();
+    /// tokio::spawn(async move {
+    ///     notifier.notify("ping", "").await?;
+    /// });
+    /// while let Ok(procedure) = receiver.recv().await { ... }
+    /// ```
+    pub fn split(self) -> (SocketIoReceiver<S>, Notifier) {
+        let notifier = self.notifier();
+        (SocketIoReceiver { socket: self }, notifier)
+    }
+}
+
+impl<S> SocketIoReceiver<S> {
+    /// Returns the per-connection state, see [`SocketIo::state`].
+    pub fn state(&self) -> &S {
+        self.socket.state()
+    }
+
+    /// Receives the next `Procedure`, see [`SocketIo::recv`].
+    pub async fn recv(&mut self) -> io::Result<Procedure> {
+        self.socket.recv().await
+    }
+
+    /// Calls a rpc method on the client, see [`SocketIo::call`].
+    pub fn call(
+        &mut self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+    ) -> impl Future<Output = io::Result<Vec<u8>>> + Send + 'static {
+        self.socket.call(name, data)
+    }
+
+    /// Resets every in-flight rpc, see [`SocketIo::abort_all`].
+    pub fn abort_all(&self) {
+        self.socket.abort_all()
+    }
+
+    /// Returns the ids of the rpc calls that were reset, see [`SocketIo::abandoned_calls`].
+    pub fn abandoned_calls(&self) -> &[u32] {
+        self.socket.abandoned_calls()
+    }
+
+    /// Returns the total bytes `(read, written)` on the connection, see [`SocketIo::byte_stats`].
+    pub fn byte_stats(&self) -> (u64, u64) {
+        self.socket.byte_stats()
+    }
+
+    /// Returns another `Notifier` for sending notifications.
+    pub fn notifier(&self) -> Notifier {
+        self.socket.notifier()
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{error::is_fatal, Procedure, SocketIo};
+use futures_core::Stream;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+type Recv<S> = Pin<Box<dyn Future<Output = (SocketIo<S>, io::Result<Procedure>)> + Send>>;
+
+fn recv<S: Send + 'static>(mut socket: SocketIo<S>) -> Recv<S> {
+    Box::pin(async move {
+        let result = socket.recv().await;
+        (socket, result)
+    })
+}
+
+/// A [`Stream`] of incoming procedures, created by [`SocketIo::into_stream`].
+///
+/// Yields the same errors as [`SocketIo::recv`], the stream ends after a fatal
+/// error (see [`is_fatal`](crate::error::is_fatal)).
+///
+/// Like [`SocketIo::recv`], `next()` is cancellation safe: the pending receive is kept
+/// by the stream, so it can be used as a `tokio::select!` branch.
+pub struct ProcedureStream<S = ()> {
+    recv: Option<Recv<S>>,
+}
+
+impl<S: Send + 'static> SocketIo<S> {
+    /// Converts the `SocketIo` into a [`Stream`] of incoming procedures.
+    ///
+    /// Use [`SocketIo::notifier`] beforehand, to send notifications.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let mut procedures = socket.into_stream();
+    /// while let Some(procedure) = procedures.next().await { ... }
+    /// ```
+    pub fn into_stream(self) -> ProcedureStream<S> {
+        ProcedureStream {
+            recv: Some(recv(self)),
+        }
+    }
+}
+
+impl<S: Send + 'static> Stream for ProcedureStream<S> {
+    type Item = io::Result<Procedure>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(pending) = self.recv.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let (socket, result) = ready!(pending.as_mut().poll(cx));
+        self.recv = match &result {
+            Err(err) if is_fatal(err) => None,
+            _ => Some(recv(socket)),
+        };
+        Poll::Ready(Some(result))
+    }
+}
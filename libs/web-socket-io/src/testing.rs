@@ -0,0 +1,32 @@
+use crate::{SocketIo, SocketIoConfig, PROTOCOL_V3};
+
+/// Size of the in-memory pipe between the two ends of a [`SocketIo::pair`].
+const PIPE_SIZE: usize = 64 * 1024;
+
+impl SocketIo {
+    /// Creates two connected `SocketIo` instances over an in-memory pipe, for testing.
+    ///
+    /// The first is the server end and the second is the client end, both created with
+    /// the default [`SocketIoConfig`], speaking the latest subprotocol ([`PROTOCOL_V3`]).
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// let (mut server, client) = SocketIo::pair();
+    /// client.notify("ping", "").await?;
+    /// assert_eq!(server.recv().await?.method(), "ping");
+    /// ```
+    pub fn pair() -> (SocketIo, SocketIo) {
+        let config = SocketIoConfig {
+            protocol: PROTOCOL_V3.into(),
+            ..Default::default()
+        };
+        let (server, client) = tokio::io::duplex(PIPE_SIZE);
+        let (reader, writer) = tokio::io::split(server);
+        let server = SocketIo::with_role(reader, writer, config.clone(), false);
+
+        let (reader, writer) = tokio::io::split(client);
+        let client = SocketIo::with_role(reader, writer, config, true);
+        (server, client)
+    }
+}
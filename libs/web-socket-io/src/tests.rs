@@ -0,0 +1,2003 @@
+use super::*;
+use tokio::io::DuplexStream;
+use web_socket::MessageType;
+
+/// A raw WebSocket client, to send frames the `SocketIo` client end never would.
+struct Peer {
+    ws: WebSocket<DuplexStream>,
+}
+
+impl Peer {
+    async fn send(&mut self, frame: &[u8]) {
+        self.ws.send(frame).await.unwrap();
+    }
+
+    /// Returns the code and reason of the close frame, failing on any data message.
+    async fn recv_close(&mut self) -> (u16, String) {
+        loop {
+            match self.ws.recv().await.unwrap() {
+                Event::Close { code, reason } => return (code, reason.into()),
+                Event::Data { data, .. } => panic!("unexpected message: {data:?}"),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Sends `frame` as a fragmented binary message, split at the given offsets.
+    async fn send_fragmented(&mut self, frame: &[u8], splits: &[usize]) {
+        let mut start = 0;
+        for (i, end) in splits.iter().copied().chain([frame.len()]).enumerate() {
+            let fragment = WsFrame {
+                fin: end == frame.len(),
+                opcode: if i == 0 { 2 } else { 0 },
+                data: &frame[start..end],
+            };
+            self.ws.send(fragment).await.unwrap();
+            start = end;
+        }
+    }
+
+    /// Returns the payload of the next data message, skipping control frames.
+    async fn recv(&mut self) -> Vec<u8> {
+        loop {
+            match self.ws.recv().await.unwrap() {
+                Event::Data { data, .. } => return data.into(),
+                Event::Close { code, reason } => panic!("closed: {code} {reason}"),
+                _ => continue,
+            }
+        }
+    }
+}
+
+fn connect(builder: SocketIoBuilder) -> (SocketIo, Peer) {
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    let (reader, writer) = tokio::io::split(server);
+    let peer = Peer {
+        ws: WebSocket::client(client),
+    };
+    (builder.build(reader, writer), peer)
+}
+
+/// A `Notifier` without a writer task, to inspect the queued replies.
+fn detached_notifier(buffer: usize) -> (Notifier, tokio::sync::mpsc::Receiver<Reply>) {
+    let (tx, rx) = tokio::sync::mpsc::channel(buffer);
+    let notifier = Notifier {
+        id: ConnId::next(),
+        tx,
+        features: Features::from_protocol(PROTOCOL_V2),
+        byte_stats: Default::default(),
+        metrics: None,
+    };
+    (notifier, rx)
+}
+
+async fn next<S: futures_core::Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+fn call_frame(id: u32, method: &str, data: &[u8]) -> Vec<u8> {
+    encode_call(id, &[], method, data, false).unwrap().into()
+}
+
+fn reply_frame(frame_type: u8, id: u32, data: &[u8]) -> Vec<u8> {
+    encode_response(frame_type, id, data).into()
+}
+
+#[tokio::test]
+async fn malformed_frame_keeps_in_flight_calls() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "slow", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    peer.send(&[0x0f]).await;
+    let Err(err) = socket.recv().await else {
+        panic!("malformed frame was accepted")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!error::is_fatal(&err));
+    assert!(socket.abandoned_calls().is_empty());
+
+    res.send("done").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"done"));
+}
+
+#[tokio::test]
+async fn send_bytes_keeps_shared_buffers_intact() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    for id in 1..=3 {
+        peer.send(&call_frame(id, "get", b"")).await;
+    }
+    let mut calls = Vec::new();
+    for _ in 1..=3 {
+        calls.push(socket.recv().await.unwrap().into_call().unwrap().1);
+    }
+    let mut calls = calls.into_iter();
+
+    // unique: the header is written in place.
+    let mut buf = BytesMut::new();
+    buf.put_bytes(0, Response::HEADER_LEN);
+    buf.extend_from_slice(b"one");
+    calls
+        .next()
+        .unwrap()
+        .send_bytes(buf.freeze())
+        .await
+        .unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"one"));
+
+    // shared: the payload is copied, the other handle still sees the reserved bytes.
+    let shared = Bytes::from(b"\0\0\0\0\0two".to_vec());
+    let other = shared.clone();
+    calls.next().unwrap().send_bytes(shared).await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"two"));
+    assert_eq!(other, &b"\0\0\0\0\0two"[..]);
+
+    // no room for the header: rejected, whatever its length.
+    let short = Bytes::from_static(b"ok");
+    let err = calls.next().unwrap().send_bytes(short).await.unwrap_err();
+    assert!(matches!(err, ResponseError::MissingHeader));
+    assert_eq!(peer.recv().await, reply_frame(5, 3, b"no response"));
+}
+
+#[tokio::test]
+async fn features_follow_the_protocol() {
+    let (socket, _peer) = connect(SocketIoBuilder::new(16));
+    assert_eq!(socket.features(), Features::NONE);
+
+    let (socket, _peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V3));
+    assert!(socket.features().contains(
+        Features::WIDE_METHOD_NAMES
+            | Features::DEADLINES
+            | Features::ERROR_FRAMES
+            | Features::CHUNKED_RESPONSES
+            | Features::TRACE_CONTEXT
+    ));
+
+    let (socket, _peer) = connect(SocketIoBuilder::new(16).wide_method_names(true));
+    assert_eq!(socket.features(), Features::WIDE_METHOD_NAMES);
+}
+
+#[tokio::test]
+async fn unsupported_replies_fail_over_v1() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "a", b"")).await;
+    peer.send(&call_frame(2, "b", b"")).await;
+    let (_, first, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let (_, second, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    let mut sink = second.into_sink();
+    let err = sink.send("part").await.unwrap_err();
+    assert!(matches!(
+        err,
+        ResponseError::Unsupported(Features::CHUNKED_RESPONSES)
+    ));
+    sink.end("whole").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"whole"));
+
+    let err = first.send_error("nope").await.unwrap_err();
+    assert!(matches!(
+        err,
+        ResponseError::Unsupported(Features::ERROR_FRAMES)
+    ));
+    // No error frame is sent for the dropped response either, a v0.1 peer
+    // can only be told by closing the connection.
+    assert_eq!(peer.recv_close().await, (1011, "no response".into()));
+}
+
+#[tokio::test]
+async fn trace_contexts_need_the_feature() {
+    let traced = encode_call(1, b"trace", "get", b"", false).unwrap();
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&traced).await;
+    let Err(err) = socket.recv().await else {
+        panic!("traced frame was accepted over v0.1")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = socket.notifier().notify_traced(b"trace", "ev", b"").await;
+    assert!(matches!(
+        err,
+        Err(NotifyError::Unsupported(Features::TRACE_CONTEXT))
+    ));
+    let err = socket.call_traced(b"trace", "ask", b"").await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V3));
+    peer.send(&traced).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.trace_context(), Some(&b"trace"[..]));
+    assert_eq!(req.method(), "get");
+
+    tokio::spawn(socket.call_traced(b"trace", "ask", b"?"));
+    let frame = peer.recv().await;
+    assert_eq!(frame, encode_call(1, b"trace", "ask", b"?", false).unwrap());
+    assert_eq!(frame[..8], [0x82, 0, 0, 0, 1, 5, b't', b'r']);
+}
+
+#[tokio::test]
+async fn poisoned_lock_keeps_the_connection_alive() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "a", b"")).await;
+    let (_, res, mut reset) = socket.recv().await.unwrap().into_call().unwrap();
+
+    let resetter = socket.resetter.clone();
+    let poisoned = std::thread::spawn(move || {
+        let _calls = resetter.calls.lock().unwrap();
+        panic!("poison the in-flight calls");
+    });
+    assert!(poisoned.join().is_err());
+    assert!(socket.resetter.calls.is_poisoned());
+
+    peer.send(&reply_frame(3, 1, b"")).await;
+    peer.send(&call_frame(2, "b", b"")).await;
+    let (req, res2, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "b");
+    reset.reset().await;
+    drop(res);
+
+    res2.send("ok").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"ok"));
+}
+
+#[tokio::test]
+async fn abort_all_fails_unanswered_calls() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    for id in 1..=3 {
+        peer.send(&call_frame(id, "slow", b"")).await;
+    }
+    let mut calls = Vec::new();
+    for _ in 1..=3 {
+        calls.push(socket.recv().await.unwrap().into_call().unwrap());
+    }
+    let (_, answered, _) = calls.remove(0);
+    answered.send("done").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"done"));
+
+    socket.abort_all();
+    let mut replies = vec![peer.recv().await, peer.recv().await];
+    replies.sort();
+    assert_eq!(
+        replies,
+        [reply_frame(5, 2, b"aborted"), reply_frame(5, 3, b"aborted")]
+    );
+    for (_, res, mut reset) in calls {
+        reset.reset().await;
+        assert!(matches!(res.send("late").await, Err(ResponseError::Reset)));
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn write_timeout_drops_a_stalled_peer() {
+    let (server, _client) = tokio::io::duplex(64);
+    let (reader, writer) = tokio::io::split(server);
+    let builder = SocketIoBuilder::new(16).write_timeout(Duration::from_millis(50));
+    let socket = builder.build(reader, writer);
+    let notifier = socket.notifier();
+
+    // the peer never reads, so the first write stalls once the pipe is full.
+    let start = tokio::time::Instant::now();
+    notifier.notify("big", [0; 1024]).await.unwrap();
+    while notifier.notify("more", b"").await.is_ok() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // one timeout for the stalled write, one for the close frame.
+    assert!(start.elapsed() <= Duration::from_millis(110));
+}
+
+#[tokio::test(start_paused = true)]
+async fn write_timeout_ignores_slow_stream_sources() {
+    let builder = SocketIoBuilder::new(16).write_timeout(Duration::from_millis(50));
+    let (socket, mut peer) = connect(builder);
+    let (mut source, reader) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+        source.write_all(b"a").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        source.write_all(b"b").await.unwrap();
+    });
+    socket.notifier().notify_stream("ev", reader).await.unwrap();
+
+    let mut expected = encode_notify("ev", b"", false).unwrap().to_vec();
+    expected.extend_from_slice(b"ab");
+    assert_eq!(peer.recv().await, expected);
+}
+
+#[tokio::test]
+async fn fragmented_call_is_parsed_as_a_whole() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let frame = call_frame(7, "upload", b"first-second-third");
+    peer.send_fragmented(&frame, &[3, 16]).await;
+
+    let (req, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "upload");
+    assert_eq!(req.data(), b"first-second-third");
+    assert!(req.was_streamed());
+    res.send("ok").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 7, b"ok"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropped_call_is_reset_and_frees_its_id() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let answered = tokio::spawn(socket.call("ask", "?"));
+    assert_eq!(peer.recv().await, call_frame(1, "ask", b"?"));
+
+    let given_up = tokio::time::timeout(Duration::from_secs(1), socket.call("slow", ""));
+    assert!(given_up.await.is_err());
+    assert_eq!(peer.recv().await, call_frame(2, "slow", b""));
+    assert_eq!(peer.recv().await, reply_frame(3, 2, b""));
+    assert!(!lock(&socket.pending_calls).contains_key(&2));
+
+    // once the ids wrap around, the still pending call keeps its id.
+    socket.next_call_id = 1;
+    tokio::spawn(socket.call("next", ""));
+    assert_eq!(peer.recv().await, call_frame(2, "next", b""));
+
+    peer.send(&reply_frame(4, 1, b"yes")).await;
+    tokio::spawn(async move { socket.recv().await });
+    assert_eq!(answered.await.unwrap().unwrap(), b"yes");
+}
+
+#[tokio::test(start_paused = true)]
+async fn abort_on_reset_or_timeout_stops_the_task() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "slow", b"")).await;
+    peer.send(&call_frame(2, "slow", b"")).await;
+    let (_, _res1, timed) = socket.recv().await.unwrap().into_call().unwrap();
+    let (_, _res2, reset) = socket.recv().await.unwrap().into_call().unwrap();
+
+    let start = tokio::time::Instant::now();
+    let timeout = Duration::from_secs(5);
+    timed
+        .abort_on_reset_or_timeout(timeout, std::future::pending::<()>())
+        .await;
+    assert_eq!(start.elapsed(), timeout);
+
+    peer.send(&reply_frame(3, 2, b"")).await;
+    let recv = tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+    reset
+        .abort_on_reset_or_timeout(timeout, std::future::pending::<()>())
+        .await;
+    assert!(start.elapsed() < timeout * 2);
+    recv.abort();
+}
+
+#[tokio::test]
+async fn error_replies_go_both_ways() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    peer.send(&call_frame(1, "fail", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    res.send_error("bad input").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(5, 1, b"bad input"));
+
+    let call = tokio::spawn(socket.call("ask", ""));
+    assert_eq!(peer.recv().await, call_frame(1, "ask", b""));
+    peer.send(&reply_frame(5, 1, b"denied")).await;
+    tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+
+    let err = call.await.unwrap().unwrap_err();
+    let remote = err
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<RemoteError>()
+        .unwrap();
+    assert_eq!(&*remote.0, b"denied");
+}
+
+#[tokio::test]
+async fn dropped_response_replies_with_an_error() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    peer.send(&call_frame(1, "forgotten", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    drop(res);
+    assert_eq!(peer.recv().await, reply_frame(5, 1, b"no response"));
+
+    peer.send(&call_frame(2, "panics", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let handler = tokio::spawn(async move {
+        let _res = res;
+        panic!("handler failed");
+    });
+    assert!(handler.await.is_err());
+    assert_eq!(peer.recv().await, reply_frame(5, 2, b"handler panicked"));
+}
+
+#[tokio::test]
+async fn dropped_response_replies_even_with_a_full_channel() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(1).protocol(PROTOCOL_V2));
+    peer.send(&call_frame(1, "forgotten", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    // The writer task doesn't run in between, so the error reply finds the channel full.
+    socket.notifier().try_notify("ev", b"").unwrap();
+    drop(res);
+    assert_eq!(peer.recv().await, encode_notify("ev", b"", false).unwrap());
+    assert_eq!(peer.recv().await, reply_frame(5, 1, b"no response"));
+}
+
+#[tokio::test]
+async fn wide_method_names_need_v2() {
+    let name = "x".repeat(300);
+    let wide = encode_call(1, &[], &name, b"", true).unwrap();
+    assert_eq!(wide[0], 2 | WIDE_NAME_FLAG);
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&wide).await;
+    assert!(socket.recv().await.is_err());
+    let err = socket.notifier().notify(&name, b"").await;
+    assert!(matches!(err, Err(NotifyError::EventNameTooBig)));
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    peer.send(&wide).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), name);
+    socket.notifier().notify(&name, b"hi").await.unwrap();
+    assert_eq!(
+        peer.recv().await,
+        encode_notify(&name, b"hi", true).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn close_writes_queued_replies_first() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    notifier.notify("bye", b"").await.unwrap();
+    socket.close(4000, "done").await.unwrap();
+
+    assert_eq!(peer.recv().await, encode_notify("bye", b"", false).unwrap());
+    assert_eq!(peer.recv_close().await, (4000, "done".into()));
+    let err = notifier.notify("late", b"").await;
+    assert!(matches!(err, Err(NotifyError::ReceiverClosed)));
+}
+
+#[tokio::test]
+async fn split_halves_share_the_connection() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let (mut receiver, notifier) = socket.split();
+    tokio::spawn(async move { notifier.notify("hello", b"").await.unwrap() })
+        .await
+        .unwrap();
+    assert_eq!(
+        peer.recv().await,
+        encode_notify("hello", b"", false).unwrap()
+    );
+
+    peer.send(&call_frame(1, "echo", b"hi")).await;
+    let (req, res, _) = receiver.recv().await.unwrap().into_call().unwrap();
+    res.send(req.data()).await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"hi"));
+}
+
+#[tokio::test]
+async fn procedure_stream_ends_after_a_fatal_error() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let mut procedures = socket.into_stream();
+
+    peer.send(&encode_notify("one", b"1", false).unwrap()).await;
+    let req = next(&mut procedures)
+        .await
+        .unwrap()
+        .unwrap()
+        .into_notify()
+        .unwrap();
+    assert_eq!(req.method(), "one");
+
+    // non-fatal errors are yielded, and the stream goes on.
+    peer.send(&[0x0f]).await;
+    assert!(next(&mut procedures).await.unwrap().is_err());
+
+    drop(peer);
+    assert!(next(&mut procedures).await.unwrap().is_err());
+    assert!(next(&mut procedures).await.is_none());
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn json_helpers_round_trip() {
+    use serde_json::{json, Value};
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "add", br#"{"a":1,"b":2}"#)).await;
+    let (req, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let args: Value = req.json().unwrap();
+    res.send_json(&json!({ "sum": args["a"].as_i64().unwrap() + args["b"].as_i64().unwrap() }))
+        .await
+        .unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, br#"{"sum":3}"#));
+
+    socket
+        .notifier()
+        .notify_json("tick", &[1, 2])
+        .await
+        .unwrap();
+    assert_eq!(
+        peer.recv().await,
+        encode_notify("tick", b"[1,2]", false).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn prepared_message_is_shared_not_copied() {
+    let msg = SocketIo::prepare_notify("news", [7; 1024]).unwrap();
+    let mut inboxes = Vec::new();
+    for _ in 0..1000 {
+        let (notifier, rx) = detached_notifier(1);
+        notifier.send_prepared(&msg).await.unwrap();
+        inboxes.push(rx);
+    }
+    for mut rx in inboxes {
+        let Some(Reply::Response(buf)) = rx.recv().await else {
+            panic!("expected a response reply")
+        };
+        assert_eq!(buf.as_ptr(), msg.buf.as_ptr());
+    }
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "blob", b"payload")).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let data = req.data().as_ptr();
+    assert_eq!(req.into_bytes().as_ptr(), data);
+}
+
+#[tokio::test]
+async fn writer_stops_once_every_sender_is_dropped() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    drop(socket);
+    notifier.notify("last", b"").await.unwrap();
+    assert_eq!(
+        peer.recv().await,
+        encode_notify("last", b"", false).unwrap()
+    );
+
+    drop(notifier);
+    assert!(peer.ws.recv().await.is_err());
+}
+
+#[tokio::test]
+async fn close_joins_the_writer() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    // the writer stops after the close frame, although a `Notifier` is still alive.
+    let notifier = socket.notifier();
+    socket.close(1000, "").await.unwrap();
+
+    assert_eq!(peer.recv_close().await, (1000, String::new()));
+    assert!(peer.ws.recv().await.is_err());
+    assert!(notifier.notify("late", b"").await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn resets_wake_every_waiting_task() {
+    const CALLS: u32 = 10_000;
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let mut tasks = Vec::new();
+    for id in 1..=CALLS {
+        peer.send(&call_frame(id, "wait", b"")).await;
+        let (_, res, mut reset) = socket.recv().await.unwrap().into_call().unwrap();
+        tasks.push(tokio::spawn(async move {
+            reset.reset().await;
+            assert!(matches!(res.send("late").await, Err(ResponseError::Reset)));
+        }));
+    }
+    tokio::spawn(async move { while socket.recv().await.is_ok() {} });
+    for id in 1..=CALLS {
+        peer.send(&reply_frame(3, id, b"")).await;
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn keepalive_sends_pings_on_the_interval() {
+    let interval = Duration::from_secs(30);
+    let (_socket, mut peer) = connect(SocketIoBuilder::new(16).keepalive(interval));
+    let start = tokio::time::Instant::now();
+    for tick in 1..=2 {
+        let Event::Ping(data) = peer.ws.recv().await.unwrap() else {
+            panic!("expected a ping")
+        };
+        assert_eq!(data.len(), 8);
+        assert_eq!(start.elapsed(), interval * tick);
+    }
+}
+
+#[tokio::test]
+async fn from_config_applies_every_option() {
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    let (reader, writer) = tokio::io::split(server);
+    let config = SocketIoConfig {
+        protocol: PROTOCOL_V2.into(),
+        max_in_flight_calls: Some(1),
+        ..SocketIoConfig::new(4)
+    };
+    let mut socket = SocketIo::from_config(reader, writer, config);
+    let mut peer = Peer {
+        ws: WebSocket::client(client),
+    };
+    assert_eq!(socket.protocol(), PROTOCOL_V2);
+    assert_eq!(socket.notifier().max_capacity(), 4);
+
+    peer.send(&call_frame(1, "a", b"")).await;
+    peer.send(&call_frame(2, "b", b"")).await;
+    peer.send(&call_frame(3, "c", b"")).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "a");
+    let recv = tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+    assert_eq!(peer.recv().await, reply_frame(5, 2, b"too many requests"));
+    assert_eq!(peer.recv().await, reply_frame(5, 3, b"too many requests"));
+    recv.abort();
+}
+
+#[tokio::test]
+async fn protocol_defaults_to_v1() {
+    let (socket, _peer) = connect(SocketIoBuilder::new(16));
+    assert_eq!(socket.protocol(), PROTOCOL_V1);
+    let (socket, _peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V3));
+    assert_eq!(socket.protocol(), PROTOCOL_V3);
+    assert_eq!(socket.with_state(()).protocol(), PROTOCOL_V3);
+}
+
+#[tokio::test]
+async fn router_dispatches_streamed_calls() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    socket.stream_calls(true);
+    let router = Router::new()
+        .stream_call("upload", |_, _, mut body, res, _| async move {
+            let mut data = Vec::new();
+            body.read_to_end(&mut data).await.unwrap();
+            res.send(data.len().to_string()).await.unwrap();
+        })
+        .call("echo", |_, req, res, _| async move {
+            res.send(req.data()).await.unwrap();
+        });
+    tokio::spawn(async move { router.serve(socket).await });
+
+    let header_len = 1 + 4 + 1 + "upload".len();
+    let frame = call_frame(1, "upload", b"0123456789");
+    peer.send_fragmented(&frame, &[header_len, header_len + 4])
+        .await;
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"10"));
+
+    let frame = call_frame(2, "echo", b"0123456789");
+    peer.send_fragmented(&frame, &[header_len - 2]).await;
+    let expected = reply_frame(5, 2, b"streamed calls aren't supported");
+    assert_eq!(peer.recv().await, expected);
+
+    let frame = call_frame(3, "nope", b"0123456789");
+    peer.send_fragmented(&frame, &[header_len - 2]).await;
+    assert_eq!(peer.recv().await, reply_frame(5, 3, b"unknown method"));
+}
+
+#[tokio::test]
+async fn try_notify_reports_a_full_channel() {
+    let (notifier, rx) = detached_notifier(1);
+    notifier.try_notify("a", b"").unwrap();
+    assert!(matches!(
+        notifier.try_notify("b", b""),
+        Err(TryNotifyError::Full)
+    ));
+    let name = "x".repeat(u16::MAX as usize + 1);
+    assert!(matches!(
+        notifier.try_notify(&name, b""),
+        Err(TryNotifyError::EventNameTooBig)
+    ));
+    drop(rx);
+    assert!(matches!(
+        notifier.try_notify("c", b""),
+        Err(TryNotifyError::ReceiverClosed)
+    ));
+}
+
+#[tokio::test]
+async fn capacity_tracks_queued_replies() {
+    let (notifier, mut rx) = detached_notifier(2);
+    assert_eq!((notifier.capacity(), notifier.max_capacity()), (2, 2));
+    notifier.notify("a", b"").await.unwrap();
+    assert_eq!((notifier.capacity(), notifier.max_capacity()), (1, 2));
+    rx.recv().await.unwrap();
+    assert_eq!(notifier.capacity(), 2);
+}
+
+#[tokio::test]
+async fn notify_text_sends_a_text_frame() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    notifier.notify_text("chat", "héllo").await.unwrap();
+    let Event::Data { ty, data } = peer.ws.recv().await.unwrap() else {
+        panic!("expected a data message")
+    };
+    assert!(matches!(ty, DataType::Complete(MessageType::Text)));
+    assert_eq!(
+        *data,
+        *encode_notify("chat", "héllo".as_bytes(), false).unwrap()
+    );
+
+    let err = notifier.notify_text(&"x".repeat(128), "").await;
+    assert!(matches!(err, Err(NotifyError::InvalidText)));
+}
+
+#[tokio::test]
+async fn reissued_call_is_served_after_a_reconnect() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "idempotent", b"")).await;
+    let (_, stale, mut reset) = socket.recv().await.unwrap().into_call().unwrap();
+
+    // the connection drops, the client re-issues the call on a new one.
+    drop(peer);
+    let Err(err) = socket.recv().await else {
+        panic!("the connection is gone")
+    };
+    assert!(error::is_fatal(&err));
+    assert_eq!(socket.abandoned_calls(), [1]);
+    reset.reset().await;
+    assert!(matches!(
+        stale.send("late").await,
+        Err(ResponseError::Reset)
+    ));
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "idempotent", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    res.send("done").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"done"));
+}
+
+#[tokio::test]
+async fn duplicate_call_id_is_answered_with_an_error() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    peer.send(&call_frame(1, "slow", b"")).await;
+    peer.send(&call_frame(1, "again", b"")).await;
+    peer.send(&call_frame(2, "next", b"")).await;
+
+    let (_, first, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let (req, _next, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "next");
+    assert_eq!(peer.recv().await, reply_frame(5, 1, b"duplicate call id"));
+
+    // the original call is still in flight.
+    first.send("done").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"done"));
+}
+
+#[tokio::test]
+async fn reset_call_sends_nothing() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "slow", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    peer.send(&reply_frame(3, 1, b"")).await;
+    peer.send(&call_frame(2, "fast", b"")).await;
+    let (_, fast, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    let err = res.send("stale").await.unwrap_err();
+    assert!(matches!(err, ResponseError::Reset));
+    assert_eq!(
+        io::Error::from(err).kind(),
+        io::ErrorKind::ConnectionAborted
+    );
+    fast.send("ok").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"ok"));
+}
+
+#[tokio::test]
+async fn method_and_method_bytes_agree() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "héllo.wörld", b"data")).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "héllo.wörld");
+    assert_eq!(req.method_bytes(), "héllo.wörld".as_bytes());
+    assert_eq!(req.try_method(), Some("héllo.wörld"));
+    assert_eq!(req.data(), b"data");
+}
+
+#[tokio::test]
+async fn procedure_accessors() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&encode_notify("ping", b"", false).unwrap()).await;
+    peer.send(&call_frame(7, "add", b"")).await;
+
+    let notify = socket.recv().await.unwrap();
+    assert!(notify.is_notify());
+    assert_eq!(notify.id(), None);
+    assert_eq!(notify.method(), "ping");
+
+    let call = socket.recv().await.unwrap();
+    assert!(call.is_call());
+    assert_eq!(call.id(), Some(7));
+    assert_eq!(call.method(), "add");
+}
+
+#[tokio::test]
+async fn abort_on_reset_with_runs_cleanup_only_on_reset() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "done", b"")).await;
+    peer.send(&call_frame(2, "slow", b"")).await;
+    let (_, _done, done_ctrl) = socket.recv().await.unwrap().into_call().unwrap();
+    let (_, _slow, slow_ctrl) = socket.recv().await.unwrap().into_call().unwrap();
+    tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+
+    let cleanups = AtomicUsize::new(0);
+    let cleanup = || async { cleanups.fetch_add(1, Ordering::SeqCst) };
+
+    done_ctrl.abort_on_reset_with(async {}, cleanup()).await;
+    assert_eq!(cleanups.load(Ordering::SeqCst), 0);
+
+    peer.send(&reply_frame(3, 2, b"")).await;
+    slow_ctrl
+        .abort_on_reset_with(std::future::pending::<()>(), cleanup())
+        .await;
+    assert_eq!(cleanups.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+#[tracing_test::traced_test]
+async fn tracing_records_received_calls_and_sent_responses() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(9, "traced", b"abc")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert!(logs_contain("received procedure"));
+    assert!(logs_contain("method=\"traced\""));
+    assert!(logs_contain("len=3"));
+
+    res.send("hello").await.unwrap();
+    assert!(logs_contain("sent response"));
+    assert!(logs_contain("len=5"));
+}
+
+#[tokio::test]
+async fn metrics_tally_received_procedures() {
+    #[derive(Default)]
+    struct Tally(Mutex<Vec<String>>);
+
+    impl Metrics for Tally {
+        fn on_call(&self, method: &str) {
+            lock(&self.0).push(format!("call {method}"));
+        }
+        fn on_notify(&self, method: &str) {
+            lock(&self.0).push(format!("notify {method}"));
+        }
+        fn on_reset(&self, id: u32) {
+            lock(&self.0).push(format!("reset {id}"));
+        }
+        fn on_reject(&self, reason: RejectReason) {
+            lock(&self.0).push(format!("reject {reason:?}"));
+        }
+    }
+
+    let tally = Arc::new(Tally::default());
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).metrics(tally.clone()));
+    peer.send(&encode_notify("joined", b"", false).unwrap())
+        .await;
+    peer.send(&call_frame(1, "a", b"")).await;
+    peer.send(&call_frame(2, "b", b"")).await;
+    peer.send(&reply_frame(3, 2, b"")).await;
+    peer.send(&reply_frame(3, 99, b"")).await;
+    peer.send(&encode_notify("left", b"", false).unwrap()).await;
+
+    let mut procedures = Vec::new();
+    for _ in 0..4 {
+        procedures.push(socket.recv().await.unwrap());
+    }
+    assert_eq!(
+        *lock(&tally.0),
+        [
+            "notify joined",
+            "call a",
+            "call b",
+            "reset 2",
+            "reject UnknownCallId(99)",
+            "notify left"
+        ]
+    );
+}
+
+#[cfg(feature = "handshake")]
+#[test]
+fn handshake_signs_the_rfc_6455_example_key() {
+    use handshake::*;
+
+    assert_eq!(
+        sign(b"dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+    assert!(header_eq(Some("WebSocket"), "websocket"));
+    assert!(!header_eq(None::<&str>, "websocket"));
+    assert!(header_contains(Some("keep-alive, Upgrade"), "upgrade"));
+    assert_eq!(
+        negotiate_protocol(["websocket.io-rpc-v0.2", "websocket.io-rpc-v0.1"]),
+        Some(PROTOCOL_V2)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_timeout_is_fatal_and_closes_the_connection() {
+    let builder = || SocketIoBuilder::new(16).read_timeout(Duration::from_secs(30));
+
+    let (mut socket, mut peer) = connect(builder());
+    peer.send(&call_frame(1, "slow", b"")).await;
+    let (_, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    let Err(err) = socket.recv().await else {
+        panic!("idle connection wasn't timed out")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(error::is_fatal(&err));
+    assert_eq!(socket.abandoned_calls(), [1]);
+    assert_eq!(peer.recv_close().await, (1008, "read timed out".into()));
+
+    // Later reads fail at once, without timing out (and closing) again.
+    let start = tokio::time::Instant::now();
+    let Err(err) = socket.recv().await else {
+        panic!("timed out connection was read again")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    let (socket, _peer) = connect(builder());
+    let err = socket.serve(|_| async {}).await;
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    let (socket, _peer) = connect(builder());
+    let mut procedures = socket.into_stream();
+    assert!(next(&mut procedures).await.unwrap().is_err());
+    assert!(next(&mut procedures).await.is_none());
+}
+
+#[tokio::test]
+async fn concurrent_broadcast_prunes_closed_members() {
+    let mut room = Broadcast::new();
+    let mut open = Vec::new();
+    for key in 0..4 {
+        let (notifier, rx) = detached_notifier(1);
+        room.insert(key, notifier);
+        if key % 2 == 0 {
+            open.push(rx);
+        }
+    }
+    let mut closed = room.broadcast_concurrent("news", b"hi", 2).await.unwrap();
+    closed.sort();
+    assert_eq!(closed, [1, 3]);
+    assert_eq!(room.len(), 2);
+    for rx in &mut open {
+        assert!(matches!(rx.try_recv(), Ok(Reply::Response(_))));
+    }
+}
+
+#[tokio::test]
+async fn notifier_closed_resolves_once_the_connection_is_closed() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    let idle = tokio::time::timeout(Duration::from_millis(20), notifier.closed()).await;
+    assert!(idle.is_err());
+
+    tokio::spawn(async move { peer.recv_close().await });
+    socket.close(1000, "bye").await.unwrap();
+    notifier.closed().await;
+    let err = notifier.notify("late", b"").await.unwrap_err();
+    assert!(matches!(err, NotifyError::ReceiverClosed));
+}
+
+#[test]
+#[allow(clippy::mutable_key_type)] // hashed by `ConnId` only
+fn notifiers_compare_by_connection() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let _guard = rt.enter();
+    let (a, _peer_a) = connect(SocketIoBuilder::new(16));
+    let (b, _peer_b) = connect(SocketIoBuilder::new(16));
+
+    let (a1, a2, b1) = (a.notifier(), a.notifier(), b.notifier());
+    assert!(a1 == a2);
+    assert!(a1 != b1);
+    assert_eq!(a1.id(), a.id());
+    assert_ne!(a.id(), b.id());
+
+    let room: std::collections::HashSet<_> = [a1, a2, b1].into_iter().collect();
+    assert_eq!(room.len(), 2);
+}
+
+#[tokio::test]
+async fn chunked_call_yields_chunks_in_order() {
+    let (mut server, mut client) = SocketIo::pair();
+    let mut chunks = client.call_chunked("tail", "app.log").await.unwrap();
+    tokio::spawn(async move { client.recv().await.map(|_| ()) });
+
+    let (req, res, _) = server.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.data(), b"app.log");
+    let mut sink = res.into_sink();
+    for line in ["one", "two", "three"] {
+        sink.send(line).await.unwrap();
+    }
+    sink.end("").await.unwrap();
+
+    let mut received = Vec::new();
+    while let Some(chunk) = next(&mut chunks).await {
+        received.push(chunk.unwrap());
+    }
+    assert_eq!(received, [&b"one"[..], b"two", b"three"]);
+}
+
+#[tokio::test]
+async fn oversized_chunked_response_fails_the_call() {
+    let builder = SocketIoBuilder::new(16)
+        .protocol(PROTOCOL_V2)
+        .max_payload_size(12);
+    let (mut socket, mut peer) = connect(builder);
+    let call = tokio::spawn(socket.call("dump", ""));
+    tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+    assert_eq!(peer.recv().await, call_frame(1, "dump", b""));
+
+    peer.send(&reply_frame(6, 1, b"123456")).await;
+    peer.send(&reply_frame(6, 1, b"7890123")).await;
+    let err = call.await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let reason = err.get_ref().unwrap().downcast_ref::<RejectReason>();
+    assert_eq!(reason, Some(&RejectReason::PayloadTooLarge));
+    // the peer is told to stop producing chunks.
+    assert_eq!(peer.recv().await, reply_frame(3, 1, b""));
+}
+
+#[tokio::test]
+async fn dropping_the_response_cancels_abortable_tasks() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    peer.send(&call_frame(1, "work", b"")).await;
+    let (_, res, controller) = socket.recv().await.unwrap().into_call().unwrap();
+
+    let (alive, cancelled) = oneshot::channel::<()>();
+    let task = controller.spawn_and_abort_on_reset(async move {
+        let _alive = alive;
+        std::future::pending::<()>().await
+    });
+    drop(res);
+
+    task.await.unwrap();
+    assert!(cancelled.await.is_err());
+    assert_eq!(peer.recv().await, reply_frame(5, 1, b"no response"));
+}
+
+#[tokio::test]
+async fn recv_event_matches_the_close_reason() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&[0x0f]).await;
+    peer.send(&call_frame(1, "a", b"")).await;
+    peer.ws.close((4001, "logged out")).await.unwrap();
+
+    let RecvOutcome::Errored(err) = socket.recv_event().await else {
+        panic!("malformed frame was accepted")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let RecvOutcome::Procedure(procedure) = socket.recv_event().await else {
+        panic!("call wasn't received")
+    };
+    assert_eq!(procedure.method(), "a");
+    match socket.recv_event().await {
+        RecvOutcome::Closed(ConnClose { code, reason }) => {
+            assert_eq!(code, 4001);
+            assert_eq!(&*reason, "logged out");
+        }
+        _ => panic!("close wasn't received"),
+    }
+}
+
+#[tokio::test]
+async fn close_after_the_loop_sends_the_code_to_the_peer() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "last", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    res.send("bye").await.unwrap();
+
+    socket.close(1000, "done").await.unwrap();
+    // queued replies are written before the close frame.
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"bye"));
+    assert_eq!(peer.recv_close().await, (1000, "done".into()));
+}
+
+#[tokio::test]
+async fn data_len_and_is_empty() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "empty", b"")).await;
+    peer.send(&encode_notify("full", b"payload", false).unwrap())
+        .await;
+
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.data_len(), 0);
+    assert!(req.is_empty());
+
+    let Procedure::Notify(req) = socket.recv().await.unwrap() else {
+        panic!("expected a notification")
+    };
+    assert_eq!(req.data_len(), 7);
+    assert!(!req.is_empty());
+    assert_eq!(req.data_len(), req.data().len());
+}
+
+#[tokio::test]
+async fn binary_method_names_are_read_as_bytes() {
+    // `Request` frame with the 2-byte method name `ff fe`.
+    let frame = [2, 0, 0, 0, 1, 2, 0xff, 0xfe, b'x'];
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&frame).await;
+    let Err(err) = socket.recv().await else {
+        panic!("non UTF-8 method name was accepted")
+    };
+    let err = err.get_ref().unwrap().downcast_ref::<FrameError>();
+    assert_eq!(err, Some(&FrameError::InvalidUtf8Method));
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).binary_method_names(true));
+    peer.send(&frame).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method_bytes(), [0xff, 0xfe]);
+    assert_eq!(req.try_method(), None);
+    assert_eq!(req.method(), "");
+    assert_eq!(req.data(), b"x");
+}
+
+#[tokio::test]
+async fn recv_into_reuses_the_scratch_buffer() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let mut scratch = Vec::with_capacity(1024);
+    let (ptr, capacity) = (scratch.as_ptr(), scratch.capacity());
+
+    for id in 1..=3 {
+        peer.send_fragmented(&call_frame(id, "chunked", &[id as u8; 300]), &[4, 100])
+            .await;
+        let (req, _res, _) = socket
+            .recv_into(&mut scratch)
+            .await
+            .unwrap()
+            .into_call()
+            .unwrap();
+        assert!(req.was_streamed());
+        assert_eq!(req.data(), [id as u8; 300]);
+        assert!(scratch.is_empty());
+        assert_eq!((scratch.as_ptr(), scratch.capacity()), (ptr, capacity));
+    }
+}
+
+#[tokio::test]
+async fn reassembled_messages_are_moved_into_the_request() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send_fragmented(&call_frame(1, "first", b"0123456789"), &[4, 12])
+        .await;
+    peer.send(&call_frame(2, "whole", b"abc")).await;
+    peer.send_fragmented(&call_frame(3, "second", b"xyz"), &[6])
+        .await;
+
+    let mut received = Vec::new();
+    for _ in 0..3 {
+        let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+        // the reassembly buffer is handed over, not copied and kept.
+        assert_eq!(socket.stream_buf.capacity(), 0);
+        received.push((req.method().to_owned(), req.data().to_vec()));
+    }
+    assert_eq!(
+        received,
+        [
+            ("first".into(), b"0123456789".to_vec()),
+            ("whole".into(), b"abc".to_vec()),
+            ("second".into(), b"xyz".to_vec()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn config_template_builds_many_sockets() {
+    let template = SocketIoConfig {
+        protocol: PROTOCOL_V2.into(),
+        ..SocketIoConfig::new(32)
+    };
+    assert_eq!(SocketIoConfig::default().channel_buffer, 16);
+    assert!(format!("{template:?}").contains("channel_buffer: 32"));
+
+    let sockets: Vec<SocketIo> = (0..2)
+        .map(|_| {
+            let (server, _client) = tokio::io::duplex(1024);
+            let (reader, writer) = tokio::io::split(server);
+            SocketIo::from_config(reader, writer, template.clone())
+        })
+        .collect();
+    for socket in &sockets {
+        assert_eq!(socket.protocol(), PROTOCOL_V2);
+        assert_eq!(socket.notifier().max_capacity(), 32);
+    }
+    assert_ne!(sockets[0].id(), sockets[1].id());
+}
+
+#[tokio::test]
+async fn from_stream_splits_a_duplex_stream() {
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    let mut socket = SocketIo::from_stream(server, 16);
+    let mut peer = Peer {
+        ws: WebSocket::client(client),
+    };
+
+    peer.send(&call_frame(1, "echo", b"hi")).await;
+    let (req, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    res.send(req.data()).await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"hi"));
+}
+
+#[tokio::test]
+async fn into_call_and_into_notify_reject_other_variants() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    socket.stream_calls(true);
+    peer.send(&encode_notify("a", b"", false).unwrap()).await;
+    peer.send(&encode_notify("b", b"", false).unwrap()).await;
+    peer.send(&call_frame(1, "c", b"")).await;
+    peer.send(&call_frame(2, "d", b"")).await;
+    peer.send_fragmented(&call_frame(3, "e", b"body"), &[8])
+        .await;
+
+    assert!(socket.recv().await.unwrap().into_call().is_none());
+    assert_eq!(
+        socket.recv().await.unwrap().into_notify().unwrap().method(),
+        "b"
+    );
+    assert!(socket.recv().await.unwrap().into_notify().is_none());
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "d");
+    let stream_call = socket.recv().await.unwrap();
+    assert_eq!(stream_call.method(), "e");
+    assert!(stream_call.into_call().is_none());
+}
+
+#[tokio::test]
+async fn pair_sends_a_notification_across() {
+    let (mut server, mut client) = SocketIo::pair();
+    assert_eq!(server.protocol(), PROTOCOL_V3);
+    assert_eq!(client.protocol(), PROTOCOL_V3);
+
+    client.notify("ping", "hello").await.unwrap();
+    let req = server.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!((req.method(), req.data()), ("ping", &b"hello"[..]));
+
+    server.notify("pong", "").await.unwrap();
+    let req = client.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!(req.method(), "pong");
+}
+
+#[tokio::test(start_paused = true)]
+async fn pong_to_a_keepalive_ping_measures_the_rtt() {
+    let builder = SocketIoBuilder::new(16).keepalive(Duration::from_secs(15));
+    let (mut socket, mut peer) = connect(builder);
+    assert_eq!(socket.last_rtt(), None);
+
+    let ping = loop {
+        if let Event::Ping(data) = peer.ws.recv().await.unwrap() {
+            break data;
+        }
+    };
+    tokio::time::advance(Duration::from_millis(40)).await;
+    peer.ws.send_pong(&*ping).await.unwrap();
+    peer.send(&encode_notify("after", b"", false).unwrap())
+        .await;
+
+    socket.recv().await.unwrap();
+    assert_eq!(socket.last_rtt(), Some(Duration::from_millis(40)));
+}
+
+#[tokio::test]
+async fn response_is_closed_once_the_writer_stops() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "expensive", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert!(!res.is_closed());
+
+    tokio::spawn(async move { peer.recv_close().await });
+    socket.close(1000, "").await.unwrap();
+    assert!(res.is_closed());
+    let err = res.send("too late").await.unwrap_err();
+    assert!(matches!(err, ResponseError::ReceiverClosed));
+}
+
+#[cfg(feature = "fastwebsockets")]
+#[tokio::test]
+async fn fastwebsockets_source_exchanges_notifications() {
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    let frame_socket =
+        fastwebsockets::WebSocket::after_handshake(server, fastwebsockets::Role::Server);
+    let mut socket = SocketIo::from_fastwebsockets(frame_socket, 16);
+    let mut peer = Peer {
+        ws: WebSocket::client(client),
+    };
+
+    let notify = encode_notify("hello", b"world", false).unwrap();
+    peer.send(&notify).await;
+    let req = socket.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!((req.method(), req.data()), ("hello", &b"world"[..]));
+
+    let notify = encode_notify("split", b"0123456789", false).unwrap();
+    peer.send_fragmented(&notify, &[3, 10]).await;
+    let req = socket.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!((req.method(), req.data()), ("split", &b"0123456789"[..]));
+    assert!(req.was_streamed());
+
+    socket.notify("reply", b"back").await.unwrap();
+    let Event::Data { data, .. } = peer.ws.recv().await.unwrap() else {
+        panic!("expected a notification")
+    };
+    assert_eq!(&*data, &*encode_notify("reply", b"back", false).unwrap());
+}
+
+#[tokio::test]
+async fn frame_source_feeds_events_from_memory() {
+    /// Yields scripted events, then fails as if the connection dropped.
+    struct Scripted(std::collections::VecDeque<Event>);
+
+    impl FrameSource for Scripted {
+        fn recv(mut self: Box<Self>) -> RecvFrame {
+            let event = self.0.pop_front();
+            let event = event.ok_or_else(|| io::ErrorKind::UnexpectedEof.into());
+            Box::pin(async move { (self as Box<dyn FrameSource>, event) })
+        }
+    }
+
+    let data = |frame: Vec<u8>| Event::Data {
+        ty: DataType::Complete(MessageType::Binary),
+        data: frame.into(),
+    };
+    let notify = encode_notify("hello", b"world", false).unwrap().to_vec();
+    let call = call_frame(1, "first", b"");
+    let (start, end) = call.split_at(4);
+    let source = Scripted(
+        [
+            data(notify),
+            Event::Data {
+                ty: DataType::Stream(Stream::Start(MessageType::Binary)),
+                data: start.into(),
+            },
+            Event::Data {
+                ty: DataType::Stream(Stream::End(MessageType::Binary)),
+                data: end.into(),
+            },
+        ]
+        .into(),
+    );
+
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    let mut socket = SocketIo::from_source(source, server, SocketIoConfig::default());
+    let mut peer = Peer {
+        ws: WebSocket::client(client),
+    };
+
+    let req = socket.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!((req.method(), req.data()), ("hello", &b"world"[..]));
+    let (req, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "first");
+    res.send("ok").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"ok"));
+
+    let Err(err) = socket.recv().await else {
+        panic!("exhausted source yielded a procedure")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn malformed_frames_map_to_frame_errors() {
+    let v1 = Features::NONE;
+    let v3 = Features::from_protocol(PROTOCOL_V3);
+    let cases: [(&[u8], Features, FrameError); 9] = [
+        (b"", v1, FrameError::Empty),
+        (b"\x0f", v1, FrameError::UnknownType(0x0f)),
+        (b"\xa2\x00\x00\x00\x01", v1, FrameError::UnknownType(0xa2)),
+        (b"\x02\x00\x00", v1, FrameError::TruncatedId),
+        (
+            b"\x22\x00\x00\x00\x01\x00",
+            v3,
+            FrameError::TruncatedDeadline,
+        ),
+        (
+            b"\x82\x00\x00\x00\x01\x05tr",
+            v3,
+            FrameError::TruncatedTraceContext,
+        ),
+        (b"\x02\x00\x00\x00\x01", v1, FrameError::TruncatedMethodLen),
+        (
+            b"\x02\x00\x00\x00\x01\x05ab",
+            v1,
+            FrameError::TruncatedMethod,
+        ),
+        (b"\x01\x02\xff\xfe", v1, FrameError::InvalidUtf8Method),
+    ];
+    for (frame, features, expected) in cases {
+        let err = parse_frame(frame, features, false).err();
+        assert_eq!(err, Some(expected), "{frame:x?}");
+    }
+}
+
+#[tokio::test]
+async fn state_is_carried_by_the_socket() {
+    struct Session {
+        user: &'static str,
+        calls: usize,
+    }
+
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let id = socket.id();
+    let session = Session {
+        user: "alice",
+        calls: 0,
+    };
+    let mut socket = socket.with_state(session);
+    assert_eq!(socket.id(), id);
+
+    peer.send(&call_frame(1, "whoami", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    socket.state_mut().calls += 1;
+    res.send(socket.state().user).await.unwrap();
+
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"alice"));
+    assert_eq!(socket.state().calls, 1);
+}
+
+#[tokio::test]
+async fn notify_all_sends_events_in_order() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    let events: [(&str, &[u8]); 3] = [("join", b"alice"), ("join", b"bob"), ("leave", b"alice")];
+    notifier.notify_all(events).await.unwrap();
+    for (name, data) in events {
+        assert_eq!(peer.recv().await, encode_notify(name, data, false).unwrap());
+    }
+
+    let (detached, rx) = detached_notifier(16);
+    drop(rx);
+    let err = detached.notify_all(events).await.unwrap_err();
+    assert!(matches!(err, NotifyError::ReceiverClosed));
+}
+
+#[tokio::test]
+async fn batched_writes_keep_the_reply_order() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(256));
+    for id in 1..=100 {
+        peer.send(&call_frame(id, "n", b"")).await;
+    }
+    let mut responses = Vec::new();
+    for _ in 1..=100 {
+        responses.push(socket.recv().await.unwrap().into_call().unwrap().1);
+    }
+    // interleave responses and notifications, so that every batch mixes both.
+    let notifier = socket.notifier();
+    for res in responses {
+        let id = res.id();
+        res.send(id.to_be_bytes()).await.unwrap();
+        notifier.notify("n", id.to_be_bytes()).await.unwrap();
+    }
+    for id in 1..=100u32 {
+        assert_eq!(peer.recv().await, reply_frame(4, id, &id.to_be_bytes()));
+        let notify = encode_notify("n", &id.to_be_bytes(), false).unwrap();
+        assert_eq!(peer.recv().await, notify);
+    }
+}
+
+#[tokio::test]
+async fn shutdown_flushes_in_flight_replies_before_closing() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "slow", b"")).await;
+    peer.send(&call_frame(2, "queued", b"")).await;
+    let (_, slow, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let (_, queued, _) = socket.recv().await.unwrap().into_call().unwrap();
+    queued.send("first").await.unwrap();
+
+    let shutdown = tokio::spawn(socket.shutdown());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(!shutdown.is_finished());
+    slow.send("second").await.unwrap();
+    shutdown.await.unwrap().unwrap();
+
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"first"));
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"second"));
+    assert_eq!(peer.recv_close().await, (1001, "going away".into()));
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn gzip_payloads_round_trip() {
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+    use std::io::{Read, Write};
+
+    let text = b"hello hello hello hello hello".repeat(20);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&text).unwrap();
+    let gzipped = encoder.finish().unwrap();
+    assert!(gzipped.len() < text.len());
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "upper", &gzipped)).await;
+    peer.send(&call_frame(2, "plain", b"not gzip")).await;
+
+    let (req, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let data = req.data_gunzip(text.len()).unwrap();
+    assert_eq!(data, text);
+    // Inflating past the limit fails, instead of allocating whatever the payload expands to.
+    let err = req.data_gunzip(text.len() - 1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    res.send_gzip(data.to_ascii_uppercase()).await.unwrap();
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert!(req.data_gunzip(1024).is_err());
+
+    let reply = peer.recv().await;
+    assert_eq!(reply[..5], [4, 0, 0, 0, 1]);
+    let mut data = Vec::new();
+    GzDecoder::new(&reply[5..]).read_to_end(&mut data).unwrap();
+    assert_eq!(data, text.to_ascii_uppercase());
+}
+
+#[tokio::test]
+async fn dropped_recv_resumes_the_partly_read_message() {
+    use tokio::io::AsyncWriteExt;
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let frame = call_frame(1, "upload", b"payload");
+    let (head, tail) = frame.split_at(4);
+    let first = WsFrame {
+        fin: false,
+        opcode: 2,
+        data: head,
+    };
+    peer.ws.send(first).await.unwrap();
+    // Only the header of the last fragment (unmasked), so its read is left pending.
+    let header = [0x80, tail.len() as u8];
+    peer.ws.stream.write_all(&header).await.unwrap();
+
+    tokio::select! {
+        biased;
+        _ = socket.recv() => panic!("the message is incomplete"),
+        _ = tokio::task::yield_now() => {}
+    }
+
+    peer.ws.stream.write_all(tail).await.unwrap();
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.method(), "upload");
+    assert_eq!(req.data(), b"payload");
+}
+
+#[tokio::test]
+async fn calls_over_the_in_flight_limit_are_rejected() {
+    let builder = SocketIoBuilder::new(16)
+        .protocol(PROTOCOL_V2)
+        .max_in_flight_calls(2);
+    let (mut socket, mut peer) = connect(builder);
+    for id in 1..=2 {
+        peer.send(&call_frame(id, "slow", b"")).await;
+    }
+    let (_, first, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let (_, second, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    peer.send(&call_frame(3, "slow", b"")).await;
+    let recv = tokio::spawn(async move {
+        let (_, third, _) = socket.recv().await.unwrap().into_call().unwrap();
+        third.send("third").await.unwrap();
+    });
+    assert_eq!(peer.recv().await, reply_frame(5, 3, b"too many requests"));
+
+    // The earlier calls are still live, and answering one makes room for another.
+    second.send("second").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"second"));
+    peer.send(&call_frame(4, "slow", b"")).await;
+    assert_eq!(peer.recv().await, reply_frame(4, 4, b"third"));
+    recv.await.unwrap();
+    first.send("first").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"first"));
+}
+
+#[tokio::test]
+async fn parts_and_into_owned_return_the_method_and_data() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "forward", b"payload")).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    assert_eq!(req.parts(), ("forward", &b"payload"[..]));
+    let (method, data) = req.into_owned();
+    assert_eq!(method, "forward");
+    assert_eq!(data, b"payload");
+}
+
+#[tokio::test]
+async fn router_prefers_exact_then_longest_prefix_handlers() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V2));
+    let router = Router::new()
+        .call_prefix("admin.", |_, _, res, _| async move {
+            res.send("admin.*").await.unwrap();
+        })
+        .call("admin.reset", |_, _, res, _| async move {
+            res.send("admin.reset").await.unwrap();
+        })
+        .call_prefix("admin.users.", |_, _, res, _| async move {
+            res.send("admin.users.*").await.unwrap();
+        });
+    tokio::spawn(async move { router.serve(socket).await });
+
+    let dispatched = [
+        ("admin.reset", "admin.reset"),
+        ("admin.users.ban", "admin.users.*"),
+        ("admin.stats", "admin.*"),
+    ];
+    for (id, (method, handler)) in (1..).zip(dispatched) {
+        peer.send(&call_frame(id, method, b"")).await;
+        assert_eq!(peer.recv().await, reply_frame(4, id, handler.as_bytes()));
+    }
+    peer.send(&call_frame(4, "admin", b"")).await;
+    assert_eq!(peer.recv().await, reply_frame(5, 4, b"unknown method"));
+}
+
+#[tokio::test]
+async fn byte_stats_count_a_notification_round_trip() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    assert_eq!(socket.byte_stats(), (0, 0));
+
+    let frame: Vec<u8> = encode_notify("ping", b"hello", false).unwrap().into();
+    peer.send(&frame).await;
+    let req = socket.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!(req.method(), "ping");
+    notifier.notify_flushed("pong", b"world!").await.unwrap();
+    assert_eq!(peer.recv().await.len(), frame.len() + 1);
+
+    let stats = (frame.len() as u64, frame.len() as u64 + 1);
+    assert_eq!(socket.byte_stats(), stats);
+    assert_eq!(notifier.byte_stats(), stats);
+}
+
+#[tokio::test]
+async fn call_ids_of_each_direction_dont_cross_talk() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "from_client", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    // Both calls in flight use id 1.
+    let answered = tokio::spawn(socket.call("from_server", ""));
+    assert_eq!(peer.recv().await, call_frame(1, "from_server", b""));
+    peer.send(&reply_frame(4, 1, b"to server")).await;
+    let recv = tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+    assert_eq!(answered.await.unwrap().unwrap(), b"to server");
+
+    res.send("to client").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"to client"));
+    recv.abort();
+}
+
+#[cfg(feature = "tokio-util")]
+#[tokio::test]
+async fn cancellation_token_is_cancelled_on_reset() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "query", b"")).await;
+    let (_, _res, ctl) = socket.recv().await.unwrap().into_call().unwrap();
+    let token = ctl.cancellation_token();
+    assert!(!token.is_cancelled());
+
+    peer.send(&reply_frame(3, 1, b"")).await;
+    let recv = tokio::spawn(async move { socket.recv().await.map(|_| ()) });
+    token.cancelled().await;
+    // Tokens taken after the reset are cancelled already.
+    assert!(ctl.cancellation_token().is_cancelled());
+    recv.abort();
+}
+
+#[tokio::test]
+async fn backpressure_is_reported_with_the_lagging_connection() {
+    #[derive(Default)]
+    struct Lagging(Mutex<Vec<ConnId>>);
+
+    impl Metrics for Lagging {
+        fn on_backpressure(&self, conn: ConnId) {
+            lock(&self.0).push(conn);
+        }
+    }
+
+    let lagging = Arc::new(Lagging::default());
+    let (socket, _peer) = connect(SocketIoBuilder::new(1).metrics(lagging.clone()));
+    let notifier = socket.notifier();
+    // The writer task doesn't run in between, so the second notification finds the channel full.
+    notifier.try_notify("a", b"").unwrap();
+    assert!(matches!(
+        notifier.try_notify("b", b""),
+        Err(TryNotifyError::Full)
+    ));
+    assert_eq!(*lock(&lagging.0), [socket.id()]);
+}
+
+#[tokio::test]
+async fn notify_stream_sends_a_large_payload_as_fragments() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let payload: Vec<u8> = (0..4 * STREAM_FRAGMENT_SIZE).map(|i| i as u8).collect();
+    let notifier = socket.notifier();
+    let sent = payload.clone();
+    let notify = tokio::spawn(async move { notifier.notify_stream("report", &sent[..]).await });
+
+    let mut message = Vec::new();
+    let mut fragments = 0;
+    loop {
+        let Event::Data { ty, data } = peer.ws.recv().await.unwrap() else {
+            panic!("expected a fragment")
+        };
+        assert!(data.len() <= STREAM_FRAGMENT_SIZE);
+        message.extend_from_slice(&data);
+        fragments += 1;
+        match ty {
+            DataType::Stream(Stream::End(_)) => break,
+            DataType::Stream(_) => continue,
+            DataType::Complete(_) => panic!("the payload wasn't fragmented"),
+        }
+    }
+    notify.await.unwrap().unwrap();
+    assert!(fragments > 4);
+
+    // The first fragment starts with the header.
+    let mut expected = encode_notify("report", b"", false).unwrap().to_vec();
+    expected.extend_from_slice(&payload);
+    assert!(message == expected);
+}
+
+#[tokio::test]
+async fn heartbeat_frames_are_opt_in() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).heartbeats(true));
+    peer.send(&[7, 1, 2, 3]).await;
+    let Procedure::Heartbeat(data) = socket.recv().await.unwrap() else {
+        panic!("expected a heartbeat")
+    };
+    assert_eq!(data, [1, 2, 3][..]);
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&[7]).await;
+    let Err(err) = socket.recv().await else {
+        panic!("heartbeat was accepted")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn send_and_close_writes_the_response_before_the_close_frame() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "once", b"")).await;
+    let (_, res, _) = socket.recv().await.unwrap().into_call().unwrap();
+
+    res.send_and_close("done", 1000, "bye").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"done"));
+    assert_eq!(peer.recv_close().await, (1000, "bye".into()));
+}
+
+#[tokio::test]
+async fn frames_over_max_frame_size_fail_recv() {
+    let builder = SocketIoBuilder::new(16)
+        .max_payload_size(1024)
+        .max_frame_size(16);
+    let (mut socket, mut peer) = connect(builder);
+    peer.send(&call_frame(1, "ok", b"")).await;
+    assert!(socket.recv().await.unwrap().into_call().is_some());
+
+    peer.send(&call_frame(2, "too_big", &[0; 16])).await;
+    let Err(err) = socket.recv().await else {
+        panic!("oversized frame was accepted")
+    };
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    assert!(error::is_fatal(&err));
+}
+
+#[tokio::test]
+async fn serve_handles_each_procedure_until_the_connection_closes() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&encode_notify("joined", b"", false).unwrap())
+        .await;
+    peer.send(&call_frame(1, "get", b"")).await;
+    peer.send(&[0x0f]).await;
+    peer.send(&encode_notify("left", b"", false).unwrap()).await;
+    let close = WsFrame {
+        fin: true,
+        opcode: 8,
+        data: &1000_u16.to_be_bytes(),
+    };
+    peer.ws.send(close).await.unwrap();
+
+    let mut handled = Vec::new();
+    let err = socket
+        .serve(|procedure| {
+            handled.push(procedure.method().to_owned());
+            async move {
+                if let Some((_, res, _)) = procedure.into_call() {
+                    res.send("ok").await.unwrap();
+                }
+            }
+        })
+        .await;
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+    // The malformed frame is skipped.
+    assert_eq!(handled, ["joined", "get", "left"]);
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"ok"));
+}
+
+#[tokio::test]
+async fn serve_concurrent_doesnt_hold_up_fast_calls() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let release = Arc::new(tokio::sync::Notify::new());
+    let slow = release.clone();
+    tokio::spawn(socket.serve_concurrent(move |procedure| {
+        let slow = slow.clone();
+        async move {
+            let (req, res, _) = procedure.into_call().unwrap();
+            if req.method() == "slow" {
+                slow.notified().await;
+            }
+            res.send(req.method()).await.unwrap();
+        }
+    }));
+
+    peer.send(&call_frame(1, "slow", b"")).await;
+    peer.send(&call_frame(2, "fast", b"")).await;
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"fast"));
+    release.notify_one();
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"slow"));
+}
+
+#[tokio::test]
+async fn errors_convert_into_io_errors() {
+    async fn forward(notifier: &Notifier) -> io::Result<()> {
+        notifier.notify("ev", b"").await?;
+        Ok(())
+    }
+
+    let (notifier, rx) = detached_notifier(1);
+    drop(rx);
+    let err = forward(&notifier).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+    let kinds: [(io::Error, _); 6] = [
+        (error::ReceiverClosed.into(), io::ErrorKind::BrokenPipe),
+        (
+            NotifyError::EventNameTooBig.into(),
+            io::ErrorKind::InvalidInput,
+        ),
+        (
+            NotifyError::Unsupported(Features::TRACE_CONTEXT).into(),
+            io::ErrorKind::Unsupported,
+        ),
+        (
+            NotifyError::Io(io::ErrorKind::UnexpectedEof.into()).into(),
+            io::ErrorKind::UnexpectedEof,
+        ),
+        (TryNotifyError::Full.into(), io::ErrorKind::WouldBlock),
+        (
+            ResponseError::Reset.into(),
+            io::ErrorKind::ConnectionAborted,
+        ),
+    ];
+    for (err, kind) in kinds {
+        assert_eq!(err.kind(), kind, "{err}");
+    }
+}
+
+#[tokio::test]
+async fn was_streamed_tells_fragmented_calls_apart() {
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16));
+    peer.send(&call_frame(1, "upload", b"payload")).await;
+    peer.send_fragmented(&call_frame(2, "upload", b"payload"), &[4])
+        .await;
+
+    let (req, ..) = socket.recv().await.unwrap().into_call().unwrap();
+    assert!(!req.was_streamed());
+    let (req, ..) = socket.recv().await.unwrap().into_call().unwrap();
+    assert!(req.was_streamed());
+    assert_eq!(req.data(), b"payload");
+}
+
+#[tokio::test]
+async fn orphan_responses_are_dropped_as_unknown_call_ids() {
+    #[derive(Default)]
+    struct Rejects(Mutex<Vec<RejectReason>>);
+
+    impl Metrics for Rejects {
+        fn on_reject(&self, reason: RejectReason) {
+            lock(&self.0).push(reason);
+        }
+    }
+
+    let rejects = Arc::new(Rejects::default());
+    let builder = SocketIoBuilder::new(16)
+        .protocol(PROTOCOL_V2)
+        .metrics(rejects.clone());
+    let (mut socket, mut peer) = connect(builder);
+
+    // A late response, to a call that gave up already.
+    let given_up = tokio::time::timeout(Duration::from_millis(1), socket.call("slow", ""));
+    assert!(given_up.await.is_err());
+    assert_eq!(peer.recv().await, call_frame(1, "slow", b""));
+    peer.send(&reply_frame(4, 1, b"late")).await;
+    peer.send(&reply_frame(5, 42, b"never called")).await;
+    peer.send(&encode_notify("next", b"", false).unwrap()).await;
+
+    let req = socket.recv().await.unwrap().into_notify().unwrap();
+    assert_eq!(req.method(), "next");
+    assert!(matches!(
+        lock(&rejects.0)[..],
+        [
+            RejectReason::UnknownCallId(1),
+            RejectReason::UnknownCallId(42)
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn weak_notifier_upgrades_until_the_last_strong_one_is_dropped() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let notifier = socket.notifier();
+    let weak = notifier.downgrade();
+    assert_eq!(weak.id(), socket.id());
+    drop(socket);
+
+    let upgraded = weak.upgrade().unwrap();
+    assert!(upgraded == notifier);
+    upgraded.notify("ev", b"").await.unwrap();
+    assert_eq!(peer.recv().await, encode_notify("ev", b"", false).unwrap());
+
+    drop(upgraded);
+    drop(notifier);
+    assert!(weak.upgrade().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn expired_deadline_resets_the_call_and_frees_its_id() {
+    fn deadline_call(id: u32, millis: u32) -> Vec<u8> {
+        let mut frame = call_frame(id, "slow", b"");
+        frame[0] |= DEADLINE_FLAG;
+        frame.splice(5..5, millis.to_be_bytes());
+        frame
+    }
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).protocol(PROTOCOL_V3));
+    peer.send(&deadline_call(1, 100)).await;
+    peer.send(&deadline_call(2, 100)).await;
+    let (req, late, mut ctl) = socket.recv().await.unwrap().into_call().unwrap();
+    assert_eq!(req.deadline(), Some(Duration::from_millis(100)));
+    let (_, in_time, _) = socket.recv().await.unwrap().into_call().unwrap();
+    in_time.send("ok").await.unwrap();
+    assert_eq!(peer.recv().await, reply_frame(4, 2, b"ok"));
+
+    let start = tokio::time::Instant::now();
+    ctl.reset().await;
+    assert_eq!(start.elapsed(), Duration::from_millis(100));
+    assert_eq!(peer.recv().await, reply_frame(5, 1, b"deadline exceeded"));
+    assert!(lock(&socket.resetter.calls).is_empty());
+
+    // The id is free for a new call, which the late response doesn't touch.
+    peer.send(&call_frame(1, "next", b"")).await;
+    let (_, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    assert!(matches!(late.send("late").await, Err(ResponseError::Reset)));
+    assert!(lock(&socket.resetter.calls).contains_key(&1));
+}
+
+#[tokio::test]
+async fn router_closes_v1_connections_on_unknown_methods() {
+    let (socket, mut peer) = connect(SocketIoBuilder::new(16));
+    let router = Router::new().call("echo", |_, req, res, _| async move {
+        res.send(req.data()).await.unwrap();
+    });
+    tokio::spawn(async move { router.serve(socket).await });
+
+    peer.send(&call_frame(1, "echo", b"hi")).await;
+    assert_eq!(peer.recv().await, reply_frame(4, 1, b"hi"));
+    peer.send(&call_frame(2, "nope", b"")).await;
+    assert_eq!(peer.recv_close().await, (1008, "unknown method".into()));
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn gunzip_stops_at_max_len() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&vec![0; 16 * 1024 * 1024]).unwrap();
+    let bomb = encoder.finish().unwrap();
+    assert!(bomb.len() < 64 * 1024);
+
+    let (mut socket, mut peer) = connect(SocketIoBuilder::new(16).max_payload_size(64 * 1024));
+    peer.send(&call_frame(1, "upload", &bomb)).await;
+    let (req, _res, _) = socket.recv().await.unwrap().into_call().unwrap();
+    let err = req.data_gunzip(64 * 1024).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}